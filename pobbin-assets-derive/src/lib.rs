@@ -0,0 +1,341 @@
+//! Derive macro for the `pobbin_assets::Row` trait.
+
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::{
+    meta::ParseNestedMeta, parse_macro_input, parse_quote, spanned::Spanned, Data, DeriveInput,
+    Fields, GenericParam, Ident, LitInt, LitStr, Path, Type,
+};
+
+/// Derives `pobbin_assets::Row` for a struct of DAT columns.
+///
+/// The file is set with `#[row(file = "Data/Words.datc64")]`, every field is annotated
+/// with its column type, e.g. `#[column(string)]`, `#[column(list(u32))]` or a path to a
+/// custom `Column` implementation. Offsets are computed from the column sizes, unknown
+/// columns are skipped with `#[column(u32, skip = 24)]`, which skips 24 bytes before the field.
+///
+/// The struct may have a single lifetime for borrowed columns, type and const parameters
+/// and where clauses are not supported.
+#[proc_macro_derive(Row, attributes(row, column))]
+pub fn derive_row(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+
+    let mut file = None;
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("row"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("file") {
+                file = Some(meta.value()?.parse::<LitStr>()?);
+                Ok(())
+            } else {
+                Err(meta.error("unsupported row attribute"))
+            }
+        })?;
+    }
+    let file = file.ok_or_else(|| {
+        syn::Error::new(
+            Span::call_site(),
+            "missing `#[row(file = \"...\")]` attribute",
+        )
+    })?;
+
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new(
+            Span::call_site(),
+            "Row can only be derived for structs",
+        ));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(syn::Error::new(
+            data.fields.span(),
+            "Row can only be derived for structs with named fields",
+        ));
+    };
+
+    let generics = &input.generics;
+    if let Some(param) = generics
+        .params
+        .iter()
+        .find(|param| !matches!(param, GenericParam::Lifetime(_)))
+    {
+        return Err(syn::Error::new(
+            param.span(),
+            "Row can only be derived for structs without type or const parameters",
+        ));
+    }
+    if let Some(where_clause) = &generics.where_clause {
+        return Err(syn::Error::new(
+            where_clause.span(),
+            "Row can only be derived for structs without a where clause",
+        ));
+    }
+
+    let (impl_generics, self_ty, item_ty) = match generics.lifetimes().count() {
+        0 => (quote!(), quote!(#name), quote!(#name)),
+        1 => (quote!(<'__row>), quote!(#name<'__row>), quote!(#name<'a>)),
+        _ => {
+            return Err(syn::Error::new(
+                generics.span(),
+                "Row can only be derived for structs with at most one lifetime",
+            ))
+        }
+    };
+
+    let mut offset = quote!(0usize);
+    let mut parse = Vec::new();
+    let mut idents = Vec::new();
+    for field in &fields.named {
+        let ident = field.ident.as_ref().unwrap();
+        let column = Column::from_field(field)?;
+
+        let ty = &column.ty;
+        let skip = column.skip;
+        offset = quote!(#offset + #skip);
+        parse.push(quote! {
+            let #ident = <#ty as ::pobbin_assets::Column>::parse(data, #offset, var_data)?;
+        });
+        offset = quote!(#offset + <#ty as ::pobbin_assets::Column>::SIZE);
+        idents.push(ident);
+    }
+
+    Ok(quote! {
+        impl #impl_generics ::pobbin_assets::Row for #self_ty {
+            const FILE: &'static str = #file;
+
+            type Item<'a> = #item_ty;
+
+            fn parse<'a>(
+                data: &'a [u8],
+                var_data: ::pobbin_assets::VarDataReader<'a>,
+            ) -> ::std::result::Result<Self::Item<'a>, ::pobbin_assets::ParseError> {
+                #(#parse)*
+
+                ::std::result::Result::Ok(#name { #(#idents),* })
+            }
+        }
+    })
+}
+
+struct Column {
+    /// Type implementing `pobbin_assets::Column`.
+    ty: Type,
+    /// Bytes skipped before the column.
+    skip: usize,
+}
+
+impl Column {
+    fn from_field(field: &syn::Field) -> syn::Result<Self> {
+        let mut ty = None;
+        let mut skip = 0;
+
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("column"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("skip") && meta.input.peek(syn::Token![=]) {
+                    skip = meta.value()?.parse::<LitInt>()?.base10_parse()?;
+                } else if ty.is_none() {
+                    ty = Some(column_type(&meta)?);
+                } else {
+                    return Err(meta.error("duplicate column type"));
+                }
+                Ok(())
+            })?;
+        }
+
+        let ty = ty.ok_or_else(|| {
+            syn::Error::new(field.span(), "missing `#[column(...)]` type attribute")
+        })?;
+
+        Ok(Self { ty, skip })
+    }
+}
+
+/// Resolves a column type, lowercase names refer to the builtin `pobbin_assets::column` types,
+/// arguments in parentheses are passed as generic arguments.
+fn column_type(meta: &ParseNestedMeta) -> syn::Result<Type> {
+    let path = match meta.path.get_ident() {
+        Some(ident)
+            if ident
+                .to_string()
+                .starts_with(|c: char| c.is_ascii_lowercase()) =>
+        {
+            let ident = Ident::new(&camel_case(&ident.to_string()), ident.span());
+            parse_quote!(::pobbin_assets::column::#ident)
+        }
+        _ => meta.path.clone(),
+    };
+
+    if !meta.input.peek(syn::token::Paren) {
+        return Ok(Type::Path(syn::TypePath { qself: None, path }));
+    }
+
+    let mut args = Vec::new();
+    meta.parse_nested_meta(|meta| {
+        args.push(column_type(&meta)?);
+        Ok(())
+    })?;
+
+    let path: Path = path;
+    Ok(parse_quote!(#path<#(#args),*>))
+}
+
+fn camel_case(s: &str) -> String {
+    s.split('_')
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(input: DeriveInput) -> String {
+        expand(input).unwrap_err().to_string()
+    }
+
+    /// Parses the column of a field with the attribute `#[column(#column)]`.
+    fn column(column: TokenStream) -> Column {
+        let input: DeriveInput = parse_quote!(
+            struct Test {
+                #[column(#column)]
+                a: u32,
+            }
+        );
+        let Data::Struct(data) = input.data else {
+            unreachable!()
+        };
+        Column::from_field(data.fields.iter().next().unwrap()).unwrap()
+    }
+
+    fn column_ty(column: TokenStream) -> String {
+        let ty = self::column(column).ty;
+        quote!(#ty).to_string().replace(' ', "")
+    }
+
+    #[test]
+    fn generics() {
+        let err = error(parse_quote! {
+            #[row(file = "Data/Test.datc64")]
+            struct Test<T> { #[column(u32)] a: T }
+        });
+        assert!(err.contains("without type or const parameters"), "{err}");
+
+        let err = error(parse_quote! {
+            #[row(file = "Data/Test.datc64")]
+            struct Test<'a, const N: usize> { #[column(string)] a: DatString<'a> }
+        });
+        assert!(err.contains("without type or const parameters"), "{err}");
+
+        let err = error(parse_quote! {
+            #[row(file = "Data/Test.datc64")]
+            struct Test<'a> where 'a: 'static { #[column(string)] a: DatString<'a> }
+        });
+        assert!(err.contains("without a where clause"), "{err}");
+
+        let err = error(parse_quote! {
+            #[row(file = "Data/Test.datc64")]
+            struct Test<'a, 'b> { #[column(string)] a: DatString<'a> }
+        });
+        assert!(err.contains("at most one lifetime"), "{err}");
+
+        assert!(expand(parse_quote! {
+            #[row(file = "Data/Test.datc64")]
+            struct Test<'a> { #[column(string)] a: DatString<'a> }
+        })
+        .is_ok());
+    }
+
+    #[test]
+    fn row_attribute() {
+        let err = error(parse_quote! {
+            struct Test { #[column(u32)] a: u32 }
+        });
+        assert!(err.contains("missing `#[row(file"), "{err}");
+
+        let err = error(parse_quote! {
+            #[row(name = "Test")]
+            struct Test { #[column(u32)] a: u32 }
+        });
+        assert_eq!(err, "unsupported row attribute");
+    }
+
+    #[test]
+    fn shapes() {
+        let err = error(parse_quote! {
+            #[row(file = "Data/Test.datc64")]
+            enum Test { A }
+        });
+        assert_eq!(err, "Row can only be derived for structs");
+
+        let err = error(parse_quote! {
+            #[row(file = "Data/Test.datc64")]
+            struct Test(#[column(u32)] u32);
+        });
+        assert_eq!(err, "Row can only be derived for structs with named fields");
+    }
+
+    #[test]
+    fn column_attribute() {
+        let err = error(parse_quote! {
+            #[row(file = "Data/Test.datc64")]
+            struct Test { a: u32 }
+        });
+        assert!(err.contains("missing `#[column(...)]`"), "{err}");
+
+        let err = error(parse_quote! {
+            #[row(file = "Data/Test.datc64")]
+            struct Test { #[column(u32, i32)] a: u32 }
+        });
+        assert_eq!(err, "duplicate column type");
+
+        let err = error(parse_quote! {
+            #[row(file = "Data/Test.datc64")]
+            struct Test { #[column(u32, skip = "4")] a: u32 }
+        });
+        assert!(err.contains("expected integer literal"), "{err}");
+    }
+
+    #[test]
+    fn column_types() {
+        assert_eq!(column_ty(quote!(u32)), "::pobbin_assets::column::U32");
+        assert_eq!(
+            column_ty(quote!(foreign_row(Words))),
+            "::pobbin_assets::column::ForeignRow<Words>"
+        );
+        assert_eq!(
+            column_ty(quote!(list(list(u32)), skip = 8)),
+            "::pobbin_assets::column::List<::pobbin_assets::column::List<::pobbin_assets::column::U32>>"
+        );
+        assert_eq!(column_ty(quote!(Color)), "Color");
+        assert_eq!(
+            column_ty(quote!(list(crate::Color))),
+            "::pobbin_assets::column::List<crate::Color>"
+        );
+    }
+
+    #[test]
+    fn skip() {
+        assert_eq!(column(quote!(u32, skip = 24)).skip, 24);
+        assert_eq!(column(quote!(skip = 4, u32)).skip, 4);
+        assert_eq!(column(quote!(u32)).skip, 0);
+    }
+}
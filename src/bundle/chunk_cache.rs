@@ -0,0 +1,208 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// Key of a cached chunk in the LRU order, the bundle name is shared with the chunk map.
+type Key = (Arc<str>, usize);
+/// Cached chunks of a bundle by chunk index, with the tick of their last use.
+type Chunks = HashMap<usize, (u64, Arc<[u8]>)>;
+
+/// Size bounded LRU cache of decompressed bundle chunks.
+///
+/// Chunks are keyed by the bundle name and the index of the chunk within the bundle,
+/// the least recently used chunks are evicted once the cache exceeds its maximum size.
+pub struct ChunkCache {
+    max_size: usize,
+    inner: Mutex<Inner>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Default)]
+struct Inner {
+    size: usize,
+    tick: u64,
+    /// Cached chunks by bundle name and chunk index, looked up without allocating a key.
+    bundles: HashMap<Arc<str>, Chunks>,
+    lru: BTreeMap<u64, Key>,
+}
+
+impl Inner {
+    fn remove(&mut self, (bundle_name, chunk): &Key) -> Option<(u64, Arc<[u8]>)> {
+        let chunks = self.bundles.get_mut(bundle_name)?;
+        let removed = chunks.remove(chunk);
+        if chunks.is_empty() {
+            self.bundles.remove(bundle_name);
+        }
+        removed
+    }
+}
+
+impl ChunkCache {
+    /// Creates a new cache which holds at most `max_size` bytes of decompressed chunks.
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            max_size,
+            inner: Default::default(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Amount of chunks which were served from the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Amount of chunks which had to be decompressed.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Total size in bytes of all currently cached chunks.
+    pub fn size(&self) -> usize {
+        self.inner.lock().unwrap().size
+    }
+
+    pub(crate) fn get(&self, bundle_name: &str, chunk: usize) -> Option<Arc<[u8]>> {
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+
+        inner.tick += 1;
+        let Some((tick, data)) = inner
+            .bundles
+            .get_mut(bundle_name)
+            .and_then(|chunks| chunks.get_mut(&chunk))
+        else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        };
+
+        let key = inner.lru.remove(tick).expect("lru entry for cached chunk");
+        *tick = inner.tick;
+        inner.lru.insert(inner.tick, key);
+
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        Some(Arc::clone(data))
+    }
+
+    pub(crate) fn insert(&self, bundle_name: &str, chunk: usize, data: Arc<[u8]>) {
+        if data.len() > self.max_size {
+            return;
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+
+        inner.tick += 1;
+        let bundle_name = match inner.bundles.get_key_value(bundle_name) {
+            Some((name, _)) => Arc::clone(name),
+            None => Arc::from(bundle_name),
+        };
+        let old = inner
+            .bundles
+            .entry(Arc::clone(&bundle_name))
+            .or_default()
+            .insert(chunk, (inner.tick, data.clone()));
+        if let Some((tick, old)) = old {
+            inner.lru.remove(&tick);
+            inner.size -= old.len();
+        }
+        inner.lru.insert(inner.tick, (bundle_name, chunk));
+        inner.size += data.len();
+
+        while inner.size > self.max_size {
+            let Some((_, key)) = inner.lru.pop_first() else {
+                break;
+            };
+            if let Some((_, data)) = inner.remove(&key) {
+                inner.size -= data.len();
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for ChunkCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChunkCache")
+            .field("max_size", &self.max_size)
+            .field("hits", &self.hits())
+            .field("misses", &self.misses())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(len: usize) -> Arc<[u8]> {
+        vec![0; len].into()
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let cache = ChunkCache::new(30);
+        cache.insert("a", 0, chunk(10));
+        cache.insert("a", 1, chunk(10));
+        cache.insert("b", 0, chunk(10));
+
+        // Touching the oldest chunk makes `a/1` the least recently used one.
+        assert!(cache.get("a", 0).is_some());
+        cache.insert("b", 1, chunk(10));
+
+        assert!(cache.get("a", 1).is_none());
+        assert!(cache.get("a", 0).is_some());
+        assert!(cache.get("b", 0).is_some());
+        assert!(cache.get("b", 1).is_some());
+        assert_eq!(cache.size(), 30);
+    }
+
+    #[test]
+    fn respects_max_size() {
+        let cache = ChunkCache::new(25);
+        cache.insert("a", 0, chunk(10));
+        cache.insert("a", 1, chunk(10));
+        cache.insert("a", 2, chunk(10));
+        assert_eq!(cache.size(), 20);
+        assert!(cache.get("a", 0).is_none());
+
+        // Chunks bigger than the entire cache are never cached and evict nothing.
+        cache.insert("b", 0, chunk(26));
+        assert!(cache.get("b", 0).is_none());
+        assert_eq!(cache.size(), 20);
+
+        // A chunk bigger than the free space evicts as many chunks as necessary.
+        cache.insert("b", 1, chunk(25));
+        assert_eq!(cache.size(), 25);
+        assert!(cache.get("a", 1).is_none());
+        assert!(cache.get("a", 2).is_none());
+    }
+
+    #[test]
+    fn replaces_existing_chunk() {
+        let cache = ChunkCache::new(100);
+        cache.insert("a", 0, chunk(10));
+        cache.insert("a", 0, chunk(20));
+        assert_eq!(cache.size(), 20);
+        assert_eq!(cache.get("a", 0).unwrap().len(), 20);
+    }
+
+    #[test]
+    fn counts_hits_and_misses() {
+        let cache = ChunkCache::new(100);
+        assert!(cache.get("a", 0).is_none());
+        cache.insert("a", 0, chunk(10));
+        assert!(cache.get("a", 0).is_some());
+        assert!(cache.get("a", 0).is_some());
+        assert!(cache.get("a", 1).is_none());
+        assert!(cache.get("b", 0).is_none());
+
+        assert_eq!(cache.hits(), 2);
+        assert_eq!(cache.misses(), 3);
+    }
+}
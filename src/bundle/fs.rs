@@ -1,4 +1,5 @@
 use std::io::{Cursor, Read, Seek};
+use std::ops::Range;
 
 use dashmap::DashMap;
 
@@ -9,12 +10,19 @@ pub enum BundleFsError {
     #[cfg(feature = "web")]
     #[error(transparent)]
     Web(#[from] Box<ureq::Error>), // boxed because of clippy::result_large_err
+    #[cfg(feature = "web")]
+    #[error("requested bytes {}..{} but got content range {content_range:?}", requested.start, requested.end)]
+    ContentRange {
+        requested: Range<u64>,
+        content_range: Option<String>,
+    },
     #[error(transparent)]
     Dyn(#[from] Box<dyn std::error::Error + Send + Sync + 'static>),
 }
 
 enum FsRead {
     File(std::fs::File),
+    Window(FileWindow),
     // If necessary, probably should add a Box<dyn Read + Seek>
     Boxed(Box<dyn std::io::Read + Send + Sync + 'static>),
     Cursor(Cursor<Vec<u8>>),
@@ -34,6 +42,9 @@ impl FileContents {
             FsRead::File(ref mut file) => {
                 file.seek(std::io::SeekFrom::Current(n as i64))?;
             }
+            FsRead::Window(ref mut window) => {
+                window.discard(n)?;
+            }
             FsRead::Cursor(ref mut cursor) => {
                 cursor.seek(std::io::SeekFrom::Current(n as i64))?;
             }
@@ -44,12 +55,19 @@ impl FileContents {
 
         Ok(())
     }
+
+    /// Limits the contents to at most `n` bytes.
+    pub fn limit(self, n: u64) -> Self {
+        let read: Box<dyn std::io::Read + Send + Sync + 'static> = Box::new(self.take(n));
+        read.into()
+    }
 }
 
 impl std::io::Read for FileContents {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         match &mut self.inner {
             FsRead::File(ref mut read) => read.read(buf),
+            FsRead::Window(ref mut read) => read.read(buf),
             FsRead::Boxed(ref mut read) => read.read(buf),
             FsRead::Cursor(ref mut read) => read.read(buf),
         }
@@ -64,6 +82,14 @@ impl From<std::fs::File> for FileContents {
     }
 }
 
+impl From<FileWindow> for FileContents {
+    fn from(window: FileWindow) -> Self {
+        Self {
+            inner: FsRead::Window(window),
+        }
+    }
+}
+
 impl From<Box<dyn std::io::Read + Send + Sync + 'static>> for FileContents {
     fn from(read: Box<dyn std::io::Read + Send + Sync + 'static>) -> Self {
         Self {
@@ -80,43 +106,81 @@ impl From<Vec<u8>> for FileContents {
     }
 }
 
-pub trait Discard {
-    fn discard(&mut self, n: u64) -> Result<(), BundleFsError>;
+/// A seekable section of a file.
+pub(crate) struct FileWindow {
+    file: std::fs::File,
+    pos: u64,
+    end: u64,
 }
 
-impl Discard for FileContents {
-    fn discard(&mut self, n: u64) -> Result<(), BundleFsError> {
-        self.discard(n)
+impl FileWindow {
+    pub(crate) fn new(mut file: std::fs::File, range: Range<u64>) -> std::io::Result<Self> {
+        file.seek(std::io::SeekFrom::Start(range.start))?;
+        Ok(Self {
+            file,
+            pos: range.start,
+            end: range.end.max(range.start),
+        })
     }
-}
 
-impl Discard for &mut &[u8] {
-    fn discard(&mut self, n: u64) -> Result<(), BundleFsError> {
-        **self = &self[self.len().min(n as usize)..];
+    fn discard(&mut self, n: u64) -> std::io::Result<()> {
+        self.pos = self.pos.saturating_add(n).min(self.end);
+        self.file.seek(std::io::SeekFrom::Start(self.pos))?;
         Ok(())
     }
 }
 
+impl std::io::Read for FileWindow {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let max = (self.end - self.pos).min(buf.len() as u64) as usize;
+        let n = self.file.read(&mut buf[..max])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
 pub trait BundleFs {
     fn get(&self, name: &str) -> Result<FileContents, BundleFsError>;
+
+    /// Returns only the bytes in `range` of the file.
+    ///
+    /// The default implementation requests the entire file and discards everything
+    /// before the range, implementations which can do better should override it.
+    fn get_range(&self, name: &str, range: Range<u64>) -> Result<FileContents, BundleFsError> {
+        let mut file = self.get(name)?;
+        file.discard(range.start)?;
+        Ok(file.limit(range.end.saturating_sub(range.start)))
+    }
 }
 
-impl<T: BundleFs> BundleFs for &T {
+impl<T: BundleFs + ?Sized> BundleFs for &T {
     fn get(&self, name: &str) -> Result<FileContents, BundleFsError> {
-        (*self).get(name)
+        (**self).get(name)
+    }
+
+    fn get_range(&self, name: &str, range: Range<u64>) -> Result<FileContents, BundleFsError> {
+        (**self).get_range(name, range)
     }
 }
 
-impl BundleFs for &dyn BundleFs {
+impl<T: BundleFs + ?Sized> BundleFs for Box<T> {
     fn get(&self, name: &str) -> Result<FileContents, BundleFsError> {
-        (*self).get(name)
+        self.as_ref().get(name)
+    }
+
+    fn get_range(&self, name: &str, range: Range<u64>) -> Result<FileContents, BundleFsError> {
+        self.as_ref().get_range(name, range)
     }
 }
 
-impl BundleFs for Box<dyn BundleFs> {
+impl<T: BundleFs + ?Sized> BundleFs for std::sync::Arc<T> {
     fn get(&self, name: &str) -> Result<FileContents, BundleFsError> {
         self.as_ref().get(name)
     }
+
+    fn get_range(&self, name: &str, range: Range<u64>) -> Result<FileContents, BundleFsError> {
+        self.as_ref().get_range(name, range)
+    }
 }
 
 #[derive(Debug)]
@@ -138,6 +202,19 @@ impl BundleFs for LocalBundleFs {
 
 pub trait Cache {
     fn get<F: BundleFs>(&self, name: &str, producer: F) -> Result<FileContents, BundleFsError>;
+
+    /// Returns the bytes in `range` of a file.
+    ///
+    /// Served from the cache if the file is already cached, otherwise only the range is
+    /// requested from the `producer` without caching it.
+    fn get_range<F: BundleFs>(
+        &self,
+        name: &str,
+        range: Range<u64>,
+        producer: F,
+    ) -> Result<FileContents, BundleFsError> {
+        producer.get_range(name, range)
+    }
 }
 
 #[derive(Default)]
@@ -167,6 +244,21 @@ impl Cache for InMemoryCache {
 
         Ok(data.into())
     }
+
+    fn get_range<F: BundleFs>(
+        &self,
+        name: &str,
+        range: Range<u64>,
+        producer: F,
+    ) -> Result<FileContents, BundleFsError> {
+        if let Some(data) = self.0.get(name) {
+            let end = usize::try_from(range.end).map_or(data.len(), |end| end.min(data.len()));
+            let start = usize::try_from(range.start).map_or(end, |start| start.min(end));
+            return Ok(data[start..end].to_vec().into());
+        }
+
+        producer.get_range(name, range)
+    }
 }
 
 pub struct LocalCache(std::path::PathBuf);
@@ -206,6 +298,19 @@ impl Cache for LocalCache {
 
         Ok(file.into())
     }
+
+    fn get_range<F: BundleFs>(
+        &self,
+        name: &str,
+        range: Range<u64>,
+        producer: F,
+    ) -> Result<FileContents, BundleFsError> {
+        if let Ok(file) = std::fs::File::open(self.0.join(name)) {
+            return Ok(FileWindow::new(file, range)?.into());
+        }
+
+        producer.get_range(name, range)
+    }
 }
 
 pub struct CacheBundleFs<F: BundleFs, C: Cache> {
@@ -223,6 +328,10 @@ impl<F: BundleFs, C: Cache> BundleFs for CacheBundleFs<F, C> {
     fn get(&self, name: &str) -> Result<FileContents, BundleFsError> {
         self.cache.get(name, &self.inner)
     }
+
+    fn get_range(&self, name: &str, range: Range<u64>) -> Result<FileContents, BundleFsError> {
+        self.cache.get_range(name, range, &self.inner)
+    }
 }
 
 #[cfg(feature = "web")]
@@ -252,7 +361,347 @@ mod web {
                 .map_err(Box::new)?;
             Ok(response.into_reader().into())
         }
+
+        fn get_range(&self, name: &str, range: Range<u64>) -> Result<FileContents, BundleFsError> {
+            if range.is_empty() {
+                return Ok(Vec::new().into());
+            }
+
+            tracing::info!(
+                name,
+                "requesting bytes {}..{} of file from web fs: {name}",
+                range.start,
+                range.end
+            );
+            let response = match ureq::get(&format!("{}{name}", self.base))
+                .set("Range", &format!("bytes={}-{}", range.start, range.end - 1))
+                .call()
+            {
+                Ok(response) => response,
+                // Some servers refuse ranges which extend past the end of the file,
+                // retry with the range clamped to the size from `bytes */size`.
+                Err(ureq::Error::Status(416, response)) => {
+                    let size = response
+                        .header("Content-Range")
+                        .and_then(|cr| cr.strip_prefix("bytes */"))
+                        .and_then(|size| size.parse::<u64>().ok());
+                    return match size {
+                        Some(size) if range.start < size && size < range.end => {
+                            self.get_range(name, range.start..size)
+                        }
+                        _ => Ok(Vec::new().into()),
+                    };
+                }
+                Err(err) => return Err(Box::new(err).into()),
+            };
+
+            // Servers are allowed to ignore the range and respond with the entire file instead.
+            let is_partial = response.status() == 206;
+            if is_partial {
+                let content_range = response.header("Content-Range");
+                if !content_range.is_some_and(|cr| is_content_range_of(cr, &range)) {
+                    return Err(BundleFsError::ContentRange {
+                        requested: range,
+                        content_range: content_range.map(ToOwned::to_owned),
+                    });
+                }
+            }
+
+            let mut contents: FileContents = response.into_reader().into();
+            if !is_partial {
+                tracing::debug!(name, "web fs does not support range requests");
+                contents.discard(range.start)?;
+            }
+
+            Ok(contents.limit(range.end - range.start))
+        }
+    }
+
+    /// Whether a `Content-Range` header, `bytes start-end/size`, serves the requested range.
+    ///
+    /// The end may be before the requested end if the file is shorter than requested.
+    pub(super) fn is_content_range_of(content_range: &str, range: &Range<u64>) -> bool {
+        let parse = || {
+            let (start, rest) = content_range.strip_prefix("bytes ")?.split_once('-')?;
+            let (end, size) = rest.split_once('/')?;
+            let (start, end) = (start.parse::<u64>().ok()?, end.parse::<u64>().ok()?);
+            let size = match size {
+                "*" => None,
+                size => Some(size.parse::<u64>().ok()?),
+            };
+            Some((start, end, size))
+        };
+
+        let Some((start, end, size)) = parse() else {
+            return false;
+        };
+
+        start == range.start
+            && start <= end
+            && (end + 1 == range.end || (end + 1 < range.end && Some(end + 1) == size))
     }
 }
 #[cfg(feature = "web")]
 pub use web::*;
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// Records which methods were called on it.
+    struct RecordingFs {
+        data: Vec<u8>,
+        calls: Mutex<Vec<&'static str>>,
+    }
+
+    impl RecordingFs {
+        fn new(data: Vec<u8>) -> Self {
+            Self {
+                data,
+                calls: Mutex::default(),
+            }
+        }
+
+        fn take_calls(&self) -> Vec<&'static str> {
+            std::mem::take(&mut self.calls.lock().unwrap())
+        }
+    }
+
+    impl BundleFs for RecordingFs {
+        fn get(&self, _name: &str) -> Result<FileContents, BundleFsError> {
+            self.calls.lock().unwrap().push("get");
+            Ok(self.data.clone().into())
+        }
+
+        fn get_range(&self, _name: &str, range: Range<u64>) -> Result<FileContents, BundleFsError> {
+            self.calls.lock().unwrap().push("get_range");
+            Ok(self.data[range.start as usize..range.end as usize]
+                .to_vec()
+                .into())
+        }
+    }
+
+    fn read(mut contents: FileContents) -> Vec<u8> {
+        let mut data = Vec::new();
+        contents.read_to_end(&mut data).unwrap();
+        data
+    }
+
+    fn data() -> Vec<u8> {
+        (0..100).collect()
+    }
+
+    #[test]
+    fn default_get_range_discards() {
+        struct Plain;
+        impl BundleFs for Plain {
+            fn get(&self, _name: &str) -> Result<FileContents, BundleFsError> {
+                Ok(data().into())
+            }
+        }
+
+        assert_eq!(read(Plain.get_range("a", 10..20).unwrap()), data()[10..20]);
+        assert_eq!(read(Plain.get_range("a", 95..120).unwrap()), data()[95..]);
+    }
+
+    #[test]
+    fn in_memory_cache_forwards_range_on_miss() {
+        let inner = RecordingFs::new(data());
+        let fs = CacheBundleFs::new(&inner, InMemoryCache::new());
+
+        assert_eq!(read(fs.get_range("a", 10..20).unwrap()), data()[10..20]);
+        assert_eq!(inner.take_calls(), ["get_range"]);
+
+        assert_eq!(read(fs.get("a").unwrap()), data());
+        assert_eq!(inner.take_calls(), ["get"]);
+
+        assert_eq!(read(fs.get_range("a", 10..20).unwrap()), data()[10..20]);
+        assert_eq!(read(fs.get_range("a", 90..200).unwrap()), data()[90..]);
+        assert!(inner.take_calls().is_empty());
+    }
+
+    #[test]
+    fn local_cache_forwards_range_on_miss() {
+        let dir = tempfile::tempdir().unwrap();
+        let inner = RecordingFs::new(data());
+        let fs = CacheBundleFs::new(&inner, LocalCache::new(dir.path()));
+
+        assert_eq!(read(fs.get_range("a", 10..20).unwrap()), data()[10..20]);
+        assert_eq!(inner.take_calls(), ["get_range"]);
+
+        assert_eq!(read(fs.get("a").unwrap()), data());
+        assert_eq!(inner.take_calls(), ["get"]);
+
+        assert_eq!(read(fs.get_range("a", 10..20).unwrap()), data()[10..20]);
+        assert!(inner.take_calls().is_empty());
+    }
+
+    #[cfg(feature = "web")]
+    mod web {
+        use std::io::{BufRead, BufReader, Write};
+        use std::net::TcpListener;
+
+        use super::*;
+
+        /// How the stand-in server answers range requests.
+        #[derive(Clone, Copy)]
+        enum Server {
+            /// Serves the requested range.
+            Ranges,
+            /// Ignores the range header and serves the entire file.
+            IgnoreRanges,
+            /// Responds with a partial response for a different range.
+            WrongRange,
+            /// Refuses ranges which extend past the end of the file.
+            Strict,
+        }
+
+        /// Serves `requests` requests on a local port, returns the base url and the received
+        /// `Range` headers.
+        fn serve(
+            server: Server,
+            requests: usize,
+        ) -> (String, std::thread::JoinHandle<Vec<Option<String>>>) {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let base = format!("http://{}/", listener.local_addr().unwrap());
+
+            let handle = std::thread::spawn(move || {
+                (0..requests)
+                    .map(|_| serve_one(&listener, server))
+                    .collect()
+            });
+
+            (base, handle)
+        }
+
+        /// Serves a single request, returns the received `Range` header.
+        fn serve_one(listener: &TcpListener, server: Server) -> Option<String> {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut range = None;
+            for line in BufReader::new(&stream).lines() {
+                let line = line.unwrap();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(": ") {
+                    if name.eq_ignore_ascii_case("range") {
+                        range = Some(value.to_owned());
+                    }
+                }
+            }
+
+            let data = data();
+            let requested = range
+                .as_deref()
+                .and_then(|r| r.strip_prefix("bytes="))
+                .and_then(|r| r.split_once('-'))
+                .map(|(s, e)| (s.parse::<usize>().unwrap(), e.parse::<usize>().unwrap()));
+
+            let (status, headers, body) = match (server, requested) {
+                (Server::Strict, Some((_, end))) if end >= data.len() => {
+                    let cr = format!("bytes */{}", data.len());
+                    ("416 Range Not Satisfiable", cr, &data[..0])
+                }
+                (Server::Ranges | Server::Strict, Some((start, end))) => {
+                    let end = end.min(data.len() - 1);
+                    let cr = format!("bytes {start}-{end}/{}", data.len());
+                    ("206 Partial Content", cr, &data[start..=end])
+                }
+                (Server::WrongRange, Some(_)) => {
+                    let cr = format!("bytes 0-9/{}", data.len());
+                    ("206 Partial Content", cr, &data[..10])
+                }
+                _ => ("200 OK", String::new(), &data[..]),
+            };
+
+            let mut response = format!(
+                "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n",
+                body.len()
+            );
+            if !headers.is_empty() {
+                response.push_str(&format!("Content-Range: {headers}\r\n"));
+            }
+            response.push_str("\r\n");
+            stream.write_all(response.as_bytes()).unwrap();
+            stream.write_all(body).unwrap();
+
+            range
+        }
+
+        #[test]
+        fn range_request() {
+            let (base, server) = serve(Server::Ranges, 1);
+            let contents = WebBundleFs::new(base).get_range("a", 10..20).unwrap();
+            assert_eq!(read(contents), data()[10..20]);
+            assert_eq!(server.join().unwrap(), [Some("bytes=10-19".to_owned())]);
+        }
+
+        #[test]
+        fn range_request_past_end() {
+            let (base, server) = serve(Server::Ranges, 1);
+            let contents = WebBundleFs::new(base).get_range("a", 90..200).unwrap();
+            assert_eq!(read(contents), data()[90..]);
+            server.join().unwrap();
+        }
+
+        #[test]
+        fn range_request_past_end_refused() {
+            let (base, server) = serve(Server::Strict, 2);
+            let contents = WebBundleFs::new(base).get_range("a", 90..200).unwrap();
+            assert_eq!(read(contents), data()[90..]);
+            assert_eq!(
+                server.join().unwrap(),
+                [
+                    Some("bytes=90-199".to_owned()),
+                    Some("bytes=90-99".to_owned())
+                ]
+            );
+        }
+
+        #[test]
+        fn range_request_after_end_refused() {
+            let (base, server) = serve(Server::Strict, 1);
+            let contents = WebBundleFs::new(base).get_range("a", 100..120).unwrap();
+            assert!(read(contents).is_empty());
+            server.join().unwrap();
+        }
+
+        #[test]
+        fn range_ignored() {
+            let (base, server) = serve(Server::IgnoreRanges, 1);
+            let contents = WebBundleFs::new(base).get_range("a", 10..20).unwrap();
+            assert_eq!(read(contents), data()[10..20]);
+            server.join().unwrap();
+        }
+
+        #[test]
+        fn wrong_content_range() {
+            let (base, server) = serve(Server::WrongRange, 1);
+            let result = WebBundleFs::new(base).get_range("a", 10..20);
+            assert!(matches!(result, Err(BundleFsError::ContentRange { .. })));
+            server.join().unwrap();
+        }
+
+        #[test]
+        fn empty_range() {
+            let contents = WebBundleFs::new("http://127.0.0.1:1/")
+                .get_range("a", 10..10)
+                .unwrap();
+            assert!(read(contents).is_empty());
+        }
+
+        #[test]
+        fn content_range() {
+            assert!(is_content_range_of("bytes 10-19/100", &(10..20)));
+            assert!(is_content_range_of("bytes 10-19/*", &(10..20)));
+            assert!(is_content_range_of("bytes 90-99/100", &(90..200)));
+            assert!(!is_content_range_of("bytes 90-99/*", &(90..200)));
+            assert!(!is_content_range_of("bytes 0-9/100", &(10..20)));
+            assert!(!is_content_range_of("bytes 10-29/100", &(10..20)));
+            assert!(!is_content_range_of("bytes 10-15/100", &(10..20)));
+            assert!(!is_content_range_of("10-19/100", &(10..20)));
+        }
+    }
+}
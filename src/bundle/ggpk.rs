@@ -0,0 +1,359 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::{BufReader, Seek, SeekFrom},
+    ops::Range,
+    path::PathBuf,
+};
+
+use nom::bytes::streaming::take;
+use nom::multi::count;
+use nom::number::streaming::{le_u32, le_u64};
+use nom::sequence::{preceded, Tuple};
+use nom::IResult;
+
+use super::{
+    fs::FileWindow,
+    parse::{nom_read, ReadErr},
+    BundleFs, BundleFsError, FileContents,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum GgpkError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("failed to parse record at offset {offset}: {err}")]
+    Parse {
+        offset: u64,
+        err: nom::Err<nom::error::Error<()>>,
+    },
+    #[error("unexpected record '{}' at offset {offset}", String::from_utf8_lossy(.tag))]
+    UnexpectedRecord { offset: u64, tag: [u8; 4] },
+    #[error("invalid name in record at offset {offset}")]
+    InvalidName { offset: u64 },
+    #[error("record at offset {offset} is referenced more than once")]
+    DuplicateRecord { offset: u64 },
+}
+
+/// Bundle filesystem backed by the `Content.ggpk` of the standalone client.
+///
+/// The record tree of the pack is parsed once when opened, files are served
+/// as windows into the pack without copying them.
+#[derive(Debug)]
+pub struct GgpkBundleFs {
+    path: PathBuf,
+    files: HashMap<String, Range<u64>>,
+}
+
+impl GgpkBundleFs {
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, GgpkError> {
+        let path = path.into();
+        let mut reader = BufReader::new(std::fs::File::open(&path)?);
+
+        let Record::Ggpk { version, root } = read_record(&mut reader, 0, 0)? else {
+            return Err(GgpkError::UnexpectedRecord {
+                offset: 0,
+                tag: *b"GGPK",
+            });
+        };
+        tracing::trace!("parsing ggpk version {version} with root directory @ {root}");
+
+        let mut files = HashMap::new();
+        let mut pending = vec![(root, String::new())];
+        // A corrupt pack can contain directory cycles.
+        let mut visited = HashSet::new();
+
+        while let Some((offset, prefix)) = pending.pop() {
+            if !visited.insert(offset) {
+                return Err(GgpkError::DuplicateRecord { offset });
+            }
+
+            match read_record(&mut reader, offset, version)? {
+                Record::Directory { name, entries } => {
+                    let prefix = match name.is_empty() {
+                        true => prefix,
+                        false => format!("{prefix}{name}/"),
+                    };
+                    pending.extend(entries.into_iter().map(|entry| (entry, prefix.clone())));
+                }
+                Record::File { name, data } => {
+                    files.insert(format!("{prefix}{name}").to_lowercase(), data);
+                }
+                Record::Free => {}
+                Record::Ggpk { .. } => {
+                    return Err(GgpkError::UnexpectedRecord {
+                        offset,
+                        tag: *b"GGPK",
+                    })
+                }
+            }
+        }
+
+        tracing::trace!("parsed {} files from ggpk", files.len());
+
+        Ok(Self { path, files })
+    }
+
+    fn window(&self, name: &str, range: Range<u64>) -> Result<FileContents, BundleFsError> {
+        let Some(data) = self.files.get(&name.to_lowercase()) else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("file '{name}' does not exist in ggpk"),
+            )
+            .into());
+        };
+
+        let start = data.start.saturating_add(range.start).min(data.end);
+        let end = data.start.saturating_add(range.end).min(data.end);
+
+        let file = std::fs::File::open(&self.path)?;
+        Ok(FileWindow::new(file, start..end)?.into())
+    }
+}
+
+impl BundleFs for GgpkBundleFs {
+    fn get(&self, name: &str) -> Result<FileContents, BundleFsError> {
+        self.window(name, 0..u64::MAX)
+    }
+
+    fn get_range(&self, name: &str, range: Range<u64>) -> Result<FileContents, BundleFsError> {
+        self.window(name, range)
+    }
+}
+
+enum Record {
+    Ggpk { version: u32, root: u64 },
+    Directory { name: String, entries: Vec<u64> },
+    File { name: String, data: Range<u64> },
+    Free,
+}
+
+/// Size of the record header, the record length followed by the tag.
+const RECORD_HEADER_SIZE: u64 = 8;
+/// Size of the SHA-256 hash stored in directory and file records.
+const HASH_SIZE: usize = 32;
+
+fn read_record(
+    reader: &mut BufReader<std::fs::File>,
+    offset: u64,
+    version: u32,
+) -> Result<Record, GgpkError> {
+    let map_err = |err| match err {
+        ReadErr::Io(err) => GgpkError::Io(err),
+        ReadErr::Parse(err) => GgpkError::Parse { offset, err },
+    };
+
+    reader.seek(SeekFrom::Start(offset))?;
+    let (length, tag) = nom_read(
+        wrap(|input| {
+            let (input, (length, tag)) = (le_u32, take(4usize)).parse(input)?;
+            Ok((input, (length, <[u8; 4]>::try_from(tag).unwrap())))
+        }),
+        RECORD_HEADER_SIZE as usize,
+        &mut *reader,
+    )
+    .map_err(map_err)?;
+
+    let char_size = match version {
+        // The Mac client uses UTF-32 names.
+        4 => 4,
+        _ => 2,
+    };
+
+    match &tag {
+        b"GGPK" => {
+            let (version, root, _free) = nom_read(
+                wrap(|input| (le_u32, le_u64, le_u64).parse(input)),
+                20,
+                &mut *reader,
+            )
+            .map_err(map_err)?;
+
+            Ok(Record::Ggpk { version, root })
+        }
+        b"PDIR" => {
+            let (name, entries) = nom_read(
+                wrap(|input| {
+                    let (input, (name_length, entry_count, _hash)) =
+                        (le_u32, le_u32, take(HASH_SIZE)).parse(input)?;
+                    let (input, name) = take(name_length as usize * char_size)(input)?;
+                    // Request all entries at once, see `HeadPayload::parse`.
+                    let (input, entries) = take(entry_count as usize * 12)(input)?;
+                    let (_, entries) =
+                        count(preceded(le_u32, le_u64), entry_count as usize)(entries)?;
+                    Ok((input, (name.to_vec(), entries)))
+                }),
+                8 + HASH_SIZE,
+                &mut *reader,
+            )
+            .map_err(map_err)?;
+
+            let name = decode_name(&name, char_size).ok_or(GgpkError::InvalidName { offset })?;
+            Ok(Record::Directory { name, entries })
+        }
+        b"FILE" => {
+            let name = nom_read(
+                wrap(|input| {
+                    let (input, (name_length, _hash)) = (le_u32, take(HASH_SIZE)).parse(input)?;
+                    let (input, name) = take(name_length as usize * char_size)(input)?;
+                    Ok((input, name.to_vec()))
+                }),
+                4 + HASH_SIZE,
+                &mut *reader,
+            )
+            .map_err(map_err)?;
+
+            let header_size = RECORD_HEADER_SIZE + 4 + HASH_SIZE as u64 + name.len() as u64;
+            let name = decode_name(&name, char_size).ok_or(GgpkError::InvalidName { offset })?;
+            Ok(Record::File {
+                name,
+                data: offset + header_size..offset + u64::from(length).max(header_size),
+            })
+        }
+        b"FREE" => Ok(Record::Free),
+        _ => Err(GgpkError::UnexpectedRecord { offset, tag }),
+    }
+}
+
+/// Decodes a null terminated UTF-16 or UTF-32 name.
+fn decode_name(data: &[u8], char_size: usize) -> Option<String> {
+    let name = if char_size == 4 {
+        data.chunks_exact(4)
+            .map(|c| char::from_u32(u32::from_le_bytes([c[0], c[1], c[2], c[3]])))
+            .collect::<Option<String>>()?
+    } else {
+        let u16s = data
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]));
+        char::decode_utf16(u16s)
+            .collect::<Result<String, _>>()
+            .ok()?
+    };
+
+    Some(name.trim_end_matches('\0').to_owned())
+}
+
+fn wrap<O>(
+    mut parser: impl FnMut(&[u8]) -> IResult<&[u8], O>,
+) -> impl FnMut(&[u8]) -> IResult<&[u8], O, nom::error::Error<()>> {
+    move |input| parser(input).map_err(|e| e.map_input(|_| ()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use super::*;
+
+    /// Builds a version 3 pack from records appended after the `GGPK` record.
+    struct Builder(Vec<u8>);
+
+    impl Builder {
+        const GGPK_SIZE: u32 = 28;
+
+        fn new() -> Self {
+            Self(vec![0; Self::GGPK_SIZE as usize])
+        }
+
+        fn offset(&self) -> u64 {
+            self.0.len() as u64
+        }
+
+        fn record(&mut self, tag: &[u8; 4], name: &str, body: &[u8]) -> u64 {
+            let offset = self.offset();
+            let name = name
+                .encode_utf16()
+                .chain([0])
+                .flat_map(u16::to_le_bytes)
+                .collect::<Vec<_>>();
+
+            let length = 8 + 4 + HASH_SIZE + name.len() + body.len();
+            let length = if tag == b"PDIR" { length + 4 } else { length };
+
+            self.0.extend((length as u32).to_le_bytes());
+            self.0.extend(tag);
+            self.0.extend((name.len() as u32 / 2).to_le_bytes());
+            if tag == b"PDIR" {
+                self.0.extend((body.len() as u32 / 12).to_le_bytes());
+            }
+            self.0.extend([0; HASH_SIZE]);
+            self.0.extend(name);
+            self.0.extend(body);
+            offset
+        }
+
+        fn file(&mut self, name: &str, data: &[u8]) -> u64 {
+            self.record(b"FILE", name, data)
+        }
+
+        fn dir(&mut self, name: &str, entries: &[u64]) -> u64 {
+            let entries = entries
+                .iter()
+                .flat_map(|offset| [0u8; 4].into_iter().chain(offset.to_le_bytes()))
+                .collect::<Vec<_>>();
+            self.record(b"PDIR", name, &entries)
+        }
+
+        fn finish(mut self, root: u64) -> tempfile::NamedTempFile {
+            let mut header = Vec::new();
+            header.extend(Self::GGPK_SIZE.to_le_bytes());
+            header.extend(b"GGPK");
+            header.extend(3u32.to_le_bytes());
+            header.extend(root.to_le_bytes());
+            header.extend(0u64.to_le_bytes());
+            self.0[..header.len()].copy_from_slice(&header);
+
+            let mut file = tempfile::NamedTempFile::new().unwrap();
+            file.write_all(&self.0).unwrap();
+            file
+        }
+    }
+
+    fn read(contents: Result<FileContents, BundleFsError>) -> Vec<u8> {
+        let mut data = Vec::new();
+        contents.unwrap().read_to_end(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn nested_directories() {
+        let mut builder = Builder::new();
+        let index = builder.file("_.index.bin", b"index");
+        let bundle = builder.file("Data.bundle.bin", b"bundle data");
+        let nested = builder.dir("Bundles2", &[index, bundle]);
+        let other = builder.file("Other.txt", b"other");
+        let root = builder.dir("", &[nested, other]);
+        let file = builder.finish(root);
+
+        let fs = GgpkBundleFs::open(file.path()).unwrap();
+        assert_eq!(fs.files.len(), 3);
+        assert_eq!(read(fs.get("Bundles2/_.index.bin")), b"index");
+        assert_eq!(read(fs.get("bundles2/data.bundle.bin")), b"bundle data");
+        assert_eq!(
+            read(fs.get_range("Bundles2/Data.bundle.bin", 7..100)),
+            b"data"
+        );
+        assert_eq!(read(fs.get("Other.txt")), b"other");
+        assert!(fs.get("Bundles2").is_err());
+    }
+
+    #[test]
+    fn directory_cycle() {
+        let mut builder = Builder::new();
+        let root_offset = builder.offset();
+        // The nested directory is written right after the root, which has a single entry.
+        let nested_offset = root_offset + 8 + 8 + HASH_SIZE as u64 + 2 + 12;
+        builder.dir("", &[nested_offset]);
+        assert_eq!(builder.dir("Loop", &[root_offset]), nested_offset);
+        let file = builder.finish(root_offset);
+
+        let err = GgpkBundleFs::open(file.path()).unwrap_err();
+        assert!(matches!(err, GgpkError::DuplicateRecord { offset } if offset == root_offset));
+    }
+
+    #[test]
+    fn not_a_ggpk() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"not a ggpk file at all").unwrap();
+        assert!(GgpkBundleFs::open(file.path()).is_err());
+    }
+}
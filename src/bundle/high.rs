@@ -1,11 +1,16 @@
-use std::{collections::HashMap, rc::Rc};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    io::Read,
+    sync::Arc,
+};
 
 use super::{
+    chunk_cache::ChunkCache,
     ooz,
     parse::{self, PathRep},
     BundleFs,
 };
-use crate::Discard;
+use crate::HashStrategy;
 
 #[derive(Debug, thiserror::Error)]
 pub enum BundleError {
@@ -17,6 +22,35 @@ pub enum BundleError {
     Parse(nom::Err<nom::error::Error<()>>),
     #[error("failed to decompress file: {0}")]
     Decompress(i32),
+    #[error("invalid path representation at offset {offset}: {kind}")]
+    PathRep { offset: usize, kind: PathRepError },
+    #[error("bundle {0} does not exist in the index")]
+    MissingBundle(u32),
+    #[error("chunk {chunk} does not exist in bundle '{bundle}'")]
+    MissingChunk { bundle: String, chunk: usize },
+    #[error("file '{0}' was not decompressed")]
+    MissingFile(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum PathRepError {
+    #[error("payload exceeds the path data")]
+    OutOfBounds,
+    #[error("truncated command")]
+    Truncated,
+    #[error("missing null terminator")]
+    MissingTerminator,
+    #[error("invalid utf-8")]
+    InvalidUtf8,
+}
+
+impl From<ooz::DecompressionError> for BundleError {
+    fn from(err: ooz::DecompressionError) -> Self {
+        match err {
+            ooz::DecompressionError::Io(err) => Self::Io(err),
+            ooz::DecompressionError::Ooz(err) => Self::Decompress(err),
+        }
+    }
 }
 
 impl<T> From<nom::Err<nom::error::Error<T>>> for BundleError {
@@ -29,11 +63,29 @@ pub type BundleResult<T> = Result<T, BundleError>;
 
 pub struct Bundle<F: BundleFs> {
     fs: F,
+    chunk_cache: Option<Arc<ChunkCache>>,
+    hash_strategy: Option<HashStrategy>,
 }
 
 impl<F: BundleFs> Bundle<F> {
     pub fn new(fs: F) -> Self {
-        Self { fs }
+        Self {
+            fs,
+            chunk_cache: None,
+            hash_strategy: None,
+        }
+    }
+
+    /// Caches decompressed chunks for all indices created from this bundle.
+    pub fn chunk_cache(&mut self, cache: impl Into<Arc<ChunkCache>>) -> &mut Self {
+        self.chunk_cache = Some(cache.into());
+        self
+    }
+
+    /// Overrides the path hash strategy instead of detecting it from the index.
+    pub fn hash_strategy(&mut self, strategy: HashStrategy) -> &mut Self {
+        self.hash_strategy = Some(strategy);
+        self
     }
 
     pub fn index(&self) -> BundleResult<IndexBundle<&F>> {
@@ -41,13 +93,26 @@ impl<F: BundleFs> Bundle<F> {
             .fs
             .get("Bundles2/_.index.bin")
             .map_err(BundleError::Fs)?;
-        let index_file = decompress(file, None)?;
-        IndexBundle::parse(&self.fs, index_file)
+        let index_file = decompress(file)?;
+        let mut index = IndexBundle::parse(&self.fs, index_file)?;
+        index.chunk_cache = self.chunk_cache.clone();
+        index.hash_strategy = match self.hash_strategy {
+            Some(strategy) => strategy,
+            None => index.detect_hash_strategy(),
+        };
+
+        Ok(index)
     }
 }
 
+/// Parsed bundle index, used to look up and read files.
+///
+/// The index is `Send + Sync` if the filesystem is, which allows parsing it once
+/// and reading files concurrently from multiple threads.
 pub struct IndexBundle<F: BundleFs> {
     fs: F,
+    chunk_cache: Option<Arc<ChunkCache>>,
+    hash_strategy: HashStrategy,
     refs: HashMap<u64, FileRef>,
     reps: Vec<PathRep>,
     data: Vec<u8>,
@@ -63,7 +128,10 @@ impl<F: BundleFs> IndexBundle<F> {
         let mut refs = HashMap::new();
 
         for file in ib.files {
-            let bundle = &ib.bundles[file.bundle_index as usize];
+            let bundle = ib
+                .bundles
+                .get(file.bundle_index as usize)
+                .ok_or(BundleError::MissingBundle(file.bundle_index))?;
 
             refs.insert(
                 file.hash,
@@ -79,6 +147,8 @@ impl<F: BundleFs> IndexBundle<F> {
 
         Ok(Self {
             fs,
+            chunk_cache: None,
+            hash_strategy: HashStrategy::default(),
             refs,
             reps: ib.reps,
             data,
@@ -91,8 +161,7 @@ impl<F: BundleFs> IndexBundle<F> {
     }
 
     pub fn read_by_name(&self, name: &str) -> BundleResult<Option<Vec<u8>>> {
-        let hash = crate::HashStrategy::Murmur3_21_2.path(name); // TODO: make configurable
-        let Some(fref) = self.refs.get(&hash) else {
+        let Some(fref) = self.file_ref(name) else {
             tracing::warn!("file '{name}' not found in index bundle");
             return Ok(None);
         };
@@ -104,8 +173,10 @@ impl<F: BundleFs> IndexBundle<F> {
             fref.file_size
         );
 
-        let file = self.fs.get(&bundle_name).map_err(BundleError::Fs)?;
-        let content = decompress(file, Some(fref))?;
+        let (_, content) = self
+            .decompress_files(&bundle_name, vec![(name.to_owned(), fref)])?
+            .pop()
+            .ok_or_else(|| BundleError::MissingFile(name.to_owned()))?;
 
         tracing::trace!(
             "successfully loaded file '{name}' from bundle '{bundle_name}' with {} bytes",
@@ -115,11 +186,153 @@ impl<F: BundleFs> IndexBundle<F> {
         Ok(Some(content))
     }
 
-    // TODO this needs to yield `Item = Result<String>`
-    pub fn files(&self) -> BundleResult<impl Iterator<Item = String> + '_> {
-        let data = Rc::new(decompress(&mut &self.data[self.path_offset..], None)?);
+    /// Reads many files at once, fetching and decompressing each bundle only once.
+    ///
+    /// Files are yielded grouped by the bundle they are contained in and not in the
+    /// order they were requested, files which do not exist are skipped.
+    pub fn read_many<I>(
+        &self,
+        names: I,
+    ) -> impl Iterator<Item = BundleResult<(String, Vec<u8>)>> + '_
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        let mut bundles = BTreeMap::<_, Vec<_>>::new();
+        for name in names {
+            let name = name.into();
+            let Some(fref) = self.file_ref(&name) else {
+                tracing::warn!("file '{name}' not found in index bundle");
+                continue;
+            };
+
+            bundles
+                .entry(fref.bundle_name.as_str())
+                .or_default()
+                .push((name, fref));
+        }
+
+        bundles.into_iter().flat_map(|(bundle_name, files)| {
+            let bundle_name = format!("Bundles2/{bundle_name}.bundle.bin");
+            tracing::trace!("reading {} files from bundle '{bundle_name}'", files.len());
+
+            match self.decompress_files(&bundle_name, files) {
+                Ok(files) => files.into_iter().map(Ok).collect(),
+                Err(err) => vec![Err(err)],
+            }
+        })
+    }
+
+    /// The decompressed chunk cache used by this index, if configured.
+    pub fn chunk_cache(&self) -> Option<&ChunkCache> {
+        self.chunk_cache.as_deref()
+    }
+
+    fn decompress_files(
+        &self,
+        bundle_name: &str,
+        files: Vec<(String, &FileRef)>,
+    ) -> BundleResult<Vec<(String, Vec<u8>)>> {
+        decompress_files(&self.fs, self.chunk_cache(), bundle_name, files)
+    }
+
+    /// The hash strategy used to look up file paths.
+    pub fn hash_strategy(&self) -> HashStrategy {
+        self.hash_strategy
+    }
+
+    /// Detects the hash strategy by hashing a sample of the paths contained in the index
+    /// and comparing them against the known file hashes.
+    ///
+    /// Falls back to the strategy of the current game version if detection fails.
+    fn detect_hash_strategy(&self) -> HashStrategy {
+        const SAMPLE_SIZE: usize = 32;
+
+        let fallback = HashStrategy::default();
+        let paths = match self.sample_files(SAMPLE_SIZE) {
+            Ok(paths) => paths,
+            Err(err) => {
+                tracing::warn!("unable to sample paths ({err}), falling back to {fallback:?}");
+                return fallback;
+            }
+        };
+
+        self.hash_strategy_of(&paths)
+    }
+
+    /// Picks the strategy which matches the most of the sampled paths.
+    ///
+    /// Ties prefer the strategy of the current game version.
+    fn hash_strategy_of(&self, paths: &[String]) -> HashStrategy {
+        let fallback = HashStrategy::default();
+        let detected = HashStrategy::ALL
+            .into_iter()
+            .map(|strategy| {
+                let matches = paths
+                    .iter()
+                    .filter(|path| self.refs.contains_key(&strategy.path(path)))
+                    .count();
+                (strategy, matches)
+            })
+            .filter(|&(_, matches)| matches > 0)
+            .max_by_key(|&(strategy, matches)| (matches, strategy == fallback));
+
+        match detected {
+            Some((strategy, matches)) => {
+                tracing::debug!(
+                    "detected hash strategy {strategy:?} ({matches} of {} paths matched)",
+                    paths.len()
+                );
+                strategy
+            }
+            None => {
+                tracing::warn!("unable to detect hash strategy, falling back to {fallback:?}");
+                fallback
+            }
+        }
+    }
+
+    /// Lists up to `n` paths, only decompressing the first chunk of the path data.
+    ///
+    /// Path representations which are not fully contained in the first chunk or are
+    /// malformed are skipped.
+    fn sample_files(&self, n: usize) -> BundleResult<Vec<String>> {
+        let mut reader = &self.data[self.path_offset..];
+        let head = read_head_from(&mut reader)?;
+
+        let first_chunk = head.payload.chunk_sizes.get(..1).unwrap_or_default();
+        let data = ooz::decompress(
+            &mut reader,
+            head.payload.chunk_unpacked_size as usize,
+            first_chunk,
+            0,
+            head.payload.uncompressed_size as usize,
+        )?;
+        let data = Arc::new(data);
+
+        let paths = self
+            .reps
+            .iter()
+            .filter(|rep| rep.payload_offset as usize + rep.payload_size as usize <= data.len())
+            .flat_map(|rep| RepIter::new(data.clone(), rep).map_while(Result::ok))
+            .take(n)
+            .collect();
+
+        Ok(paths)
+    }
+
+    fn file_ref(&self, name: &str) -> Option<&FileRef> {
+        self.refs.get(&self.hash_strategy.path(name))
+    }
+
+    /// Lists the paths of all files contained in the index.
+    ///
+    /// Malformed path data yields an error and stops the listing of the affected path
+    /// representation, the remaining representations are still listed.
+    pub fn files(&self) -> BundleResult<impl Iterator<Item = BundleResult<String>> + '_> {
+        let data = Arc::new(decompress(&self.data[self.path_offset..])?);
 
-        // TODO: this could be one iterator owning reps and data without Rc but this is good enough
+        // TODO: this could be one iterator owning reps and data without Arc but this is good enough
         // for now
         let files = self.reps.iter().flat_map(move |rep| {
             let data = data.clone();
@@ -131,7 +344,7 @@ impl<F: BundleFs> IndexBundle<F> {
 }
 
 struct RepIter {
-    data: Rc<Vec<u8>>,
+    data: Arc<Vec<u8>>,
     current: usize,
     end: usize,
     base_phase: bool,
@@ -139,27 +352,34 @@ struct RepIter {
 }
 
 impl RepIter {
-    fn new(data: Rc<Vec<u8>>, rep: &PathRep) -> Self {
+    fn new(data: Arc<Vec<u8>>, rep: &PathRep) -> Self {
+        let current = rep.payload_offset as usize;
         Self {
             data,
-            current: rep.payload_offset as usize,
-            end: rep.payload_offset as usize + rep.payload_size as usize,
+            current,
+            end: current.saturating_add(rep.payload_size as usize),
             base_phase: false,
             bases: Vec::new(),
         }
     }
-}
 
-impl Iterator for RepIter {
-    type Item = String;
+    fn next_path(&mut self) -> Result<Option<String>, (usize, PathRepError)> {
+        if self.current >= self.end {
+            return Ok(None);
+        }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let mut slice = &self.data[self.current..self.end];
+        let data = self
+            .data
+            .get(..self.end)
+            .ok_or((self.current, PathRepError::OutOfBounds))?;
+
+        while self.current < self.end {
+            let offset = self.current;
 
-        while !slice.is_empty() {
-            // TODO: proper parsing
-            let cmd = u32::from_le_bytes(slice[..4].try_into().unwrap());
-            slice = &slice[4..];
+            let cmd = data
+                .get(offset..offset + 4)
+                .ok_or((offset, PathRepError::Truncated))?;
+            let cmd = u32::from_le_bytes(cmd.try_into().unwrap());
             self.current += 4;
 
             if cmd == 0 {
@@ -170,26 +390,53 @@ impl Iterator for RepIter {
                 continue;
             }
 
-            // TODO panics everywhere
-            let i = slice.iter().position(|&b| b == 0).unwrap();
-            let s = std::str::from_utf8(&slice[..i]).unwrap(); // TODO
-            slice = &slice[i + 1..];
+            let slice = &data[self.current..];
+            let i = slice
+                .iter()
+                .position(|&b| b == 0)
+                .ok_or((offset, PathRepError::MissingTerminator))?;
+            let s = std::str::from_utf8(&slice[..i])
+                .map_err(|_| (offset, PathRepError::InvalidUtf8))?;
             self.current += i + 1;
 
-            let s = if let Some(val) = self.bases.get(cmd as usize - 1) {
-                val.clone() + s
-            } else {
-                s.to_owned()
+            // A reference to a not yet existing base is a fragment without a base.
+            let base = cmd as usize - 1;
+            let s = match self.bases.get(base) {
+                Some(val) => val.clone() + s,
+                None => {
+                    if base > self.bases.len() {
+                        tracing::debug!(
+                            "reference to base {base} at {offset} but only {} bases exist",
+                            self.bases.len()
+                        );
+                    }
+                    s.to_owned()
+                }
             };
 
             if self.base_phase {
                 self.bases.push(s);
             } else {
-                return Some(s);
+                return Ok(Some(s));
             }
         }
 
-        None
+        Ok(None)
+    }
+}
+
+impl Iterator for RepIter {
+    type Item = BundleResult<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_path() {
+            Ok(path) => path.map(Ok),
+            Err((offset, kind)) => {
+                // Path data after an error can not be interpreted anymore.
+                self.current = self.end;
+                Some(Err(BundleError::PathRep { offset, kind }))
+            }
+        }
     }
 }
 
@@ -208,58 +455,514 @@ struct FileRef {
     file_size: usize,
 }
 
-fn decompress(
-    mut file: (impl std::io::Read + Discard),
-    fref: Option<&FileRef>,
-) -> BundleResult<Vec<u8>> {
-    let head = parse::Head::read(&mut file).map_err(|err| match err {
+/// Amount of bytes requested up front when reading a bundle header.
+///
+/// The header is 60 bytes plus 4 bytes per chunk, this covers bundles with up to ~1000 chunks
+/// in a single request, bigger headers are fetched with an additional request.
+const HEAD_PREFETCH: u64 = 4096;
+
+/// Reads the header of a bundle from a reader.
+fn read_head_from(file: &mut impl Read) -> BundleResult<parse::Head> {
+    parse::Head::read(file).map_err(|err| match err {
         parse::ReadErr::Io(err) => BundleError::Io(err),
         parse::ReadErr::Parse(err) => err.into(),
-    })?;
+    })
+}
+
+/// Decompresses an entire bundle from a reader.
+fn decompress(mut file: impl Read) -> BundleResult<Vec<u8>> {
+    let head = read_head_from(&mut file)?;
+
+    ooz::decompress(
+        &mut file,
+        head.payload.chunk_unpacked_size as usize,
+        &head.payload.chunk_sizes,
+        0,
+        head.payload.uncompressed_size as usize,
+    )
+    .map_err(Into::into)
+}
+
+/// Decompresses many files from the same bundle.
+///
+/// All chunks covering the files which are not already cached are requested with a single
+/// ranged read, chunks in between which are not needed are skipped and not decompressed.
+fn decompress_files(
+    fs: &impl BundleFs,
+    cache: Option<&ChunkCache>,
+    bundle_name: &str,
+    files: Vec<(String, &FileRef)>,
+) -> BundleResult<Vec<(String, Vec<u8>)>> {
+    let (head, head_size) = read_head(fs, bundle_name)?;
 
     let chunk_unpacked_size = head.payload.chunk_unpacked_size as usize;
     let uncompressed_size = head.payload.uncompressed_size as usize;
+    let chunk_sizes = &head.payload.chunk_sizes;
+
+    let missing_chunk = |chunk| BundleError::MissingChunk {
+        bundle: bundle_name.to_owned(),
+        chunk,
+    };
+    if chunk_unpacked_size == 0 {
+        return Err(missing_chunk(0));
+    }
 
-    let file_offset = fref.map(|fref| fref.file_offset).unwrap_or(0);
-    let file_size = fref.map(|fref| fref.file_size).unwrap_or(uncompressed_size);
+    let file_chunks = |fref: &FileRef| {
+        fref.file_offset / chunk_unpacked_size
+            ..(fref.file_offset + fref.file_size).div_ceil(chunk_unpacked_size)
+    };
 
-    // First chunk that includes a part of the targeted file.
-    let num_chunk_start = file_offset / chunk_unpacked_size;
-    // Last chunk that includes a part of the targeted file.
-    let num_chunk_end = div_ceil(file_offset + file_size, chunk_unpacked_size);
+    let mut chunks = BTreeMap::new();
+    let mut missing = BTreeSet::new();
+    for chunk in files.iter().flat_map(|(_, fref)| file_chunks(fref)) {
+        if chunks.contains_key(&chunk) || missing.contains(&chunk) {
+            continue;
+        }
+        if chunk >= chunk_sizes.len() {
+            return Err(missing_chunk(chunk));
+        }
 
-    let chunks_start: usize = head.payload.chunk_sizes[..num_chunk_start]
-        .iter()
-        .map(|&s| s as usize)
-        .sum();
+        match cache.and_then(|cache| cache.get(bundle_name, chunk)) {
+            Some(data) => {
+                chunks.insert(chunk, data);
+            }
+            None => {
+                missing.insert(chunk);
+            }
+        }
+    }
 
-    file.discard(chunks_start as u64).map_err(BundleError::Fs)?;
+    if let (Some(&first), Some(&last)) = (missing.first(), missing.last()) {
+        // Offset of each chunk within the bundle.
+        let chunk_offsets = std::iter::once(head_size)
+            .chain(chunk_sizes.iter().scan(head_size, |offset, &size| {
+                *offset += size as u64;
+                Some(*offset)
+            }))
+            .collect::<Vec<_>>();
+
+        // All missing chunks are checked to exist in the bundle above.
+        let offset = |chunk: usize| {
+            chunk_offsets
+                .get(chunk)
+                .copied()
+                .ok_or(missing_chunk(chunk))
+        };
 
-    let mut content = ooz::decompress(
-        &mut file,
-        chunk_unpacked_size,
-        &head.payload.chunk_sizes[num_chunk_start..num_chunk_end],
-        num_chunk_start,
-        uncompressed_size,
-    )
-    .map_err(|err| match err {
-        ooz::DecompressionError::Io(err) => BundleError::Io(err),
-        ooz::DecompressionError::Ooz(err) => BundleError::Decompress(err),
-    })?;
-
-    // If the file does not starts at the beginning of the buffer,
-    // we have to move its contents to the start of the buffer first
-    // and then truncate the buffer to the file size.
-    // The file offset is always 0 when `fref` is `None` (entire file decompressed).
-    if file_offset > 0 {
-        let file_decompress_start = file_offset - num_chunk_start * chunk_unpacked_size;
-        content.copy_within(file_decompress_start..file_decompress_start + file_size, 0);
-    }
-
-    content.truncate(file_size);
-    Ok(content)
+        let mut file = fs
+            .get_range(bundle_name, offset(first)?..offset(last + 1)?)
+            .map_err(BundleError::Fs)?;
+
+        let mut position = first;
+        for chunk in missing {
+            file.discard(offset(chunk)? - offset(position)?)
+                .map_err(BundleError::Fs)?;
+
+            let chunk_size = chunk_sizes
+                .get(chunk..chunk + 1)
+                .ok_or(missing_chunk(chunk))?;
+            if chunk * chunk_unpacked_size >= uncompressed_size {
+                return Err(missing_chunk(chunk));
+            }
+
+            let data: Arc<[u8]> = ooz::decompress(
+                &mut file,
+                chunk_unpacked_size,
+                chunk_size,
+                chunk,
+                uncompressed_size,
+            )?
+            .into();
+
+            if let Some(cache) = cache {
+                cache.insert(bundle_name, chunk, Arc::clone(&data));
+            }
+            chunks.insert(chunk, data);
+
+            position = chunk + 1;
+        }
+    }
+
+    files
+        .into_iter()
+        .map(|(name, fref)| {
+            let file_end = fref.file_offset + fref.file_size;
+
+            let mut content = Vec::with_capacity(fref.file_size);
+            for chunk in file_chunks(fref) {
+                let chunk_offset = chunk * chunk_unpacked_size;
+                let start = fref.file_offset.max(chunk_offset) - chunk_offset;
+                let end = file_end.min(chunk_offset + chunk_unpacked_size) - chunk_offset;
+                let data = chunks
+                    .get(&chunk)
+                    .and_then(|data| data.get(start..end))
+                    .ok_or(missing_chunk(chunk))?;
+                content.extend_from_slice(data);
+            }
+
+            Ok((name, content))
+        })
+        .collect()
+}
+
+/// Reads the header of a bundle using ranged reads.
+///
+/// Returns the parsed header and its size in bytes.
+fn read_head(fs: &impl BundleFs, bundle_name: &str) -> BundleResult<(parse::Head, u64)> {
+    let mut data = Vec::with_capacity(HEAD_PREFETCH as usize);
+    fs.get_range(bundle_name, 0..HEAD_PREFETCH)
+        .map_err(BundleError::Fs)?
+        .read_to_end(&mut data)
+        .map_err(BundleError::Io)?;
+
+    loop {
+        let to_read = match parse::Head::parse(&data) {
+            Ok((rem, head)) => return Ok((head, (data.len() - rem.len()) as u64)),
+            Err(nom::Err::Incomplete(nom::Needed::Unknown)) => 1,
+            Err(nom::Err::Incomplete(nom::Needed::Size(len))) => len.get() as u64,
+            Err(err) => return Err(err.into()),
+        };
+
+        let start = data.len() as u64;
+        let read = fs
+            .get_range(bundle_name, start..start + to_read)
+            .map_err(BundleError::Fs)?
+            .read_to_end(&mut data)
+            .map_err(BundleError::Io)?;
+
+        if read == 0 {
+            return Err(BundleError::Io(std::io::ErrorKind::UnexpectedEof.into()));
+        }
+    }
 }
 
-fn div_ceil(a: usize, b: usize) -> usize {
-    (a + b - 1) / b
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BundleFsError, FileContents};
+
+    struct MemoryFs(HashMap<&'static str, Vec<u8>>);
+
+    impl BundleFs for MemoryFs {
+        fn get(&self, name: &str) -> Result<FileContents, BundleFsError> {
+            match self.0.get(name) {
+                Some(data) => Ok(data.clone().into()),
+                None => Err(std::io::Error::from(std::io::ErrorKind::NotFound).into()),
+            }
+        }
+    }
+
+    /// An uncompressed index with a single bundle named `data`.
+    fn index(files: &[(&str, u32, u32, u32)]) -> Vec<u8> {
+        let files = files
+            .iter()
+            .map(|&(name, bundle_index, offset, size)| {
+                let hash = HashStrategy::default().path(name);
+                (hash, bundle_index, offset, size)
+            })
+            .collect::<Vec<_>>();
+        index_of(&files)
+    }
+
+    /// Like [`index`] but with already hashed file paths.
+    fn index_of(files: &[(u64, u32, u32, u32)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend(1u32.to_le_bytes());
+        data.extend(4u32.to_le_bytes());
+        data.extend(b"data");
+        data.extend(0u32.to_le_bytes());
+
+        data.extend((files.len() as u32).to_le_bytes());
+        for &(hash, bundle_index, offset, size) in files {
+            data.extend(hash.to_le_bytes());
+            data.extend(bundle_index.to_le_bytes());
+            data.extend(offset.to_le_bytes());
+            data.extend(size.to_le_bytes());
+        }
+
+        data.extend(0u32.to_le_bytes());
+        data
+    }
+
+    /// A bundle header without the compressed chunks.
+    fn bundle_head(
+        uncompressed_size: u32,
+        chunk_unpacked_size: u32,
+        chunk_sizes: &[u32],
+    ) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend(uncompressed_size.to_le_bytes());
+        data.extend(chunk_sizes.iter().sum::<u32>().to_le_bytes());
+        data.extend(0u32.to_le_bytes());
+        data.extend(0u32.to_le_bytes());
+        data.extend(0u32.to_le_bytes());
+        data.extend(u64::from(uncompressed_size).to_le_bytes());
+        data.extend(u64::from(chunk_sizes.iter().sum::<u32>()).to_le_bytes());
+        data.extend((chunk_sizes.len() as u32).to_le_bytes());
+        data.extend(chunk_unpacked_size.to_le_bytes());
+        data.extend([0; 16]);
+        data.extend(chunk_sizes.iter().flat_map(|size| size.to_le_bytes()));
+        data
+    }
+
+    fn open(files: &[(&str, u32, u32, u32)], head: Vec<u8>) -> BundleResult<IndexBundle<MemoryFs>> {
+        let fs = MemoryFs([("Bundles2/data.bundle.bin", head)].into());
+        IndexBundle::parse(fs, index(files))
+    }
+
+    #[test]
+    fn missing_bundle() {
+        let result = open(&[("a", 1, 0, 10)], bundle_head(10, 10, &[10]));
+        assert!(matches!(result, Err(BundleError::MissingBundle(1))));
+    }
+
+    #[test]
+    fn file_past_last_chunk() {
+        let index = open(&[("a", 0, 0, 30)], bundle_head(30, 10, &[10, 10])).unwrap();
+        let result = index.read_by_name("a");
+        assert!(matches!(
+            result,
+            Err(BundleError::MissingChunk { chunk: 2, .. })
+        ));
+
+        let result = index.read_many(["a"]).collect::<Vec<_>>();
+        assert!(matches!(
+            result[..],
+            [Err(BundleError::MissingChunk { chunk: 2, .. })]
+        ));
+    }
+
+    #[test]
+    fn chunk_past_uncompressed_size() {
+        let index = open(&[("a", 0, 20, 5)], bundle_head(15, 10, &[10, 10, 10])).unwrap();
+        let result = index.read_by_name("a");
+        assert!(matches!(
+            result,
+            Err(BundleError::MissingChunk { chunk: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn zero_chunk_size() {
+        let index = open(&[("a", 0, 0, 10)], bundle_head(10, 0, &[10])).unwrap();
+        let result = index.read_by_name("a");
+        assert!(matches!(
+            result,
+            Err(BundleError::MissingChunk { chunk: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn missing_file() {
+        let index = open(&[], bundle_head(10, 10, &[10])).unwrap();
+        assert!(index.read_by_name("a").unwrap().is_none());
+    }
+
+    #[test]
+    fn hash_strategy_fallback_on_corrupt_paths() {
+        let mut data = index(&[("a", 0, 0, 10)]);
+        data.extend([0xff; 7]);
+        let fs = MemoryFs(HashMap::new());
+        let index = IndexBundle::parse(fs, data).unwrap();
+
+        assert!(index.sample_files(1).is_err());
+        assert_eq!(index.detect_hash_strategy(), HashStrategy::default());
+    }
+
+    /// Detects the strategy of an index containing the sample paths hashed with `strategies`.
+    fn detect(strategies: &[HashStrategy]) -> HashStrategy {
+        let paths = ["Data/BaseItemTypes.dat64", "Art/2DItems/Rings/Ring1.dds"];
+        let files = strategies
+            .iter()
+            .flat_map(|strategy| paths.map(|path| (strategy.path(path), 0, 0, 10)))
+            .collect::<Vec<_>>();
+
+        let index = IndexBundle::parse(MemoryFs(HashMap::new()), index_of(&files)).unwrap();
+        index.hash_strategy_of(&paths.map(ToOwned::to_owned))
+    }
+
+    #[test]
+    fn hash_strategy_fnv() {
+        let detected = detect(&[HashStrategy::Fnv3_11_2]);
+        assert_eq!(detected, HashStrategy::Fnv3_11_2);
+    }
+
+    #[test]
+    fn hash_strategy_murmur() {
+        let detected = detect(&[HashStrategy::Murmur3_21_2]);
+        assert_eq!(detected, HashStrategy::Murmur3_21_2);
+    }
+
+    #[test]
+    fn hash_strategy_tie_prefers_default() {
+        let detected = detect(&[HashStrategy::Fnv3_11_2, HashStrategy::Murmur3_21_2]);
+        assert_eq!(detected, HashStrategy::default());
+
+        let detected = detect(&[HashStrategy::Murmur3_21_2, HashStrategy::Fnv3_11_2]);
+        assert_eq!(detected, HashStrategy::default());
+    }
+
+    #[test]
+    fn hash_strategy_no_match() {
+        assert_eq!(detect(&[]), HashStrategy::default());
+    }
+
+    mod path_rep {
+        use super::*;
+
+        /// Encodes a path representation from commands and fragments.
+        fn payload(commands: &[(u32, &[u8])]) -> Vec<u8> {
+            let mut data = Vec::new();
+            for &(cmd, fragment) in commands {
+                data.extend(cmd.to_le_bytes());
+                if cmd != 0 {
+                    data.extend(fragment);
+                    data.push(0);
+                }
+            }
+            data
+        }
+
+        fn rep_at(offset: usize, size: usize) -> PathRep {
+            PathRep {
+                hash: 0,
+                payload_offset: offset as u32,
+                payload_size: size as u32,
+                payload_recursive_size: 0,
+            }
+        }
+
+        fn paths(data: Vec<u8>) -> Vec<Result<String, (usize, PathRepError)>> {
+            let rep = rep_at(0, data.len());
+            paths_of(data, &rep)
+        }
+
+        fn paths_of(data: Vec<u8>, rep: &PathRep) -> Vec<Result<String, (usize, PathRepError)>> {
+            RepIter::new(Arc::new(data), rep)
+                .map(|path| match path {
+                    Ok(path) => Ok(path),
+                    Err(BundleError::PathRep { offset, kind }) => Err((offset, kind)),
+                    Err(err) => panic!("unexpected error {err}"),
+                })
+                .collect()
+        }
+
+        #[test]
+        fn valid() {
+            let data = payload(&[
+                (0, b""),
+                (1, b"Art/"),
+                (1, b"Textures/"),
+                (0, b""),
+                (2, b"a.dds"),
+                (1, b"b.dds"),
+                (3, b"c.dds"),
+            ]);
+
+            let paths = paths(data);
+            assert_eq!(
+                paths,
+                [
+                    Ok("Art/Textures/a.dds".to_owned()),
+                    Ok("Art/b.dds".to_owned()),
+                    Ok("c.dds".to_owned()),
+                ]
+            );
+        }
+
+        #[test]
+        fn truncated_command() {
+            let mut data = payload(&[(1, b"a")]);
+            data.extend([1, 0]);
+            assert_eq!(
+                paths(data),
+                [Ok("a".to_owned()), Err((6, PathRepError::Truncated))]
+            );
+        }
+
+        #[test]
+        fn missing_terminator() {
+            let mut data = payload(&[(1, b"a")]);
+            data.extend(1u32.to_le_bytes());
+            data.extend(b"b");
+            assert_eq!(
+                paths(data),
+                [
+                    Ok("a".to_owned()),
+                    Err((6, PathRepError::MissingTerminator))
+                ]
+            );
+        }
+
+        #[test]
+        fn terminator_after_payload() {
+            // The terminator of the path is only part of the next representation.
+            let data = payload(&[(1, b"a"), (1, b"b")]);
+            let rep = rep_at(0, data.len() - 1);
+            assert_eq!(
+                paths_of(data, &rep),
+                [
+                    Ok("a".to_owned()),
+                    Err((6, PathRepError::MissingTerminator))
+                ]
+            );
+        }
+
+        #[test]
+        fn invalid_utf8() {
+            let data = payload(&[(1, b"a\xff")]);
+            assert_eq!(paths(data), [Err((0, PathRepError::InvalidUtf8))]);
+        }
+
+        #[test]
+        fn missing_base() {
+            let data = payload(&[
+                (0, b""),
+                (1, b"Art/"),
+                (0, b""),
+                (3, b"a.dds"),
+                (1, b"b.dds"),
+            ]);
+            assert_eq!(
+                paths(data),
+                [Ok("a.dds".to_owned()), Ok("Art/b.dds".to_owned())]
+            );
+        }
+
+        #[test]
+        fn payload_past_end() {
+            let data = payload(&[(1, b"a")]);
+            let rep = rep_at(0, data.len() + 1);
+            assert_eq!(paths_of(data, &rep), [Err((0, PathRepError::OutOfBounds))]);
+
+            let data = payload(&[(1, b"a")]);
+            let rep = rep_at(u32::MAX as usize, u32::MAX as usize);
+            let err = (u32::MAX as usize, PathRepError::OutOfBounds);
+            assert_eq!(paths_of(data, &rep), [Err(err)]);
+        }
+
+        #[test]
+        fn empty() {
+            assert!(paths(Vec::new()).is_empty());
+        }
+
+        #[test]
+        fn truncated_and_corrupted() {
+            let data = payload(&[(0, b""), (1, b"Art/"), (0, b""), (2, b"a.dds"), (1, b"b")]);
+
+            for len in 0..data.len() {
+                let paths = paths(data[..len].to_vec());
+                assert!(paths.iter().filter(|path| path.is_err()).count() <= 1);
+            }
+
+            for i in 0..data.len() {
+                for byte in [0x00, 0x01, 0x02, 0x7f, 0xff] {
+                    let mut data = data.clone();
+                    data[i] = byte;
+                    let paths = paths(data);
+                    assert!(paths.iter().filter(|path| path.is_err()).count() <= 1);
+                }
+            }
+        }
+    }
 }
@@ -1,7 +1,11 @@
+mod chunk_cache;
 mod fs;
+mod ggpk;
 mod high;
 mod ooz;
 mod parse;
 
+pub use self::chunk_cache::ChunkCache;
 pub use self::fs::*;
+pub use self::ggpk::{GgpkBundleFs, GgpkError};
 pub use self::high::*;
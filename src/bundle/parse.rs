@@ -228,9 +228,13 @@ where
             Err(nom::Err::Failure(e)) => return Err(ReadErr::Parse(nom::Err::Failure(e))),
         };
 
-        (&mut reader)
+        let read = (&mut reader)
             .take(to_read as u64)
             .read_to_end(&mut input)
             .map_err(ReadErr::Io)?;
+
+        if read == 0 {
+            return Err(ReadErr::Io(std::io::ErrorKind::UnexpectedEof.into()));
+        }
     }
 }
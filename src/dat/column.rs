@@ -0,0 +1,285 @@
+//! Column types of DAT rows, used by `#[derive(Row)]`.
+
+use std::marker::PhantomData;
+
+use super::{
+    file::VarDataReader,
+    row::ParseError,
+    utils::{parse_u32, parse_u64},
+    DatList, DatString, ForeignKey,
+};
+
+/// A column of a DAT row.
+pub trait Column {
+    /// Size of the column in a row of a `.datc64` file.
+    const SIZE: usize;
+
+    type Value<'a>;
+
+    /// Parses the column at `idx` of the row `data`.
+    fn parse<'a>(
+        data: &'a [u8],
+        idx: usize,
+        var_data: VarDataReader<'a>,
+    ) -> Result<Self::Value<'a>, ParseError>;
+}
+
+pub struct Bool;
+
+impl Column for Bool {
+    const SIZE: usize = 1;
+
+    type Value<'a> = bool;
+
+    fn parse<'a>(data: &'a [u8], idx: usize, _: VarDataReader<'a>) -> Result<bool, ParseError> {
+        data.get(idx)
+            .map(|&b| b == 1)
+            .ok_or(ParseError::NotEnoughData)
+    }
+}
+
+pub struct U32;
+
+impl Column for U32 {
+    const SIZE: usize = 4;
+
+    type Value<'a> = u32;
+
+    fn parse<'a>(data: &'a [u8], idx: usize, _: VarDataReader<'a>) -> Result<u32, ParseError> {
+        parse_u32(data, idx)
+    }
+}
+
+pub struct I32;
+
+impl Column for I32 {
+    const SIZE: usize = 4;
+
+    type Value<'a> = i32;
+
+    fn parse<'a>(data: &'a [u8], idx: usize, _: VarDataReader<'a>) -> Result<i32, ParseError> {
+        parse_u32(data, idx).map(|v| v as i32)
+    }
+}
+
+pub struct F32;
+
+impl Column for F32 {
+    const SIZE: usize = 4;
+
+    type Value<'a> = f32;
+
+    fn parse<'a>(data: &'a [u8], idx: usize, _: VarDataReader<'a>) -> Result<f32, ParseError> {
+        parse_u32(data, idx).map(f32::from_bits)
+    }
+}
+
+pub struct U64;
+
+impl Column for U64 {
+    const SIZE: usize = 8;
+
+    type Value<'a> = u64;
+
+    fn parse<'a>(data: &'a [u8], idx: usize, _: VarDataReader<'a>) -> Result<u64, ParseError> {
+        parse_u64(data, idx)
+    }
+}
+
+/// UTF-16 string in the variable data.
+pub struct String;
+
+impl Column for String {
+    const SIZE: usize = 8;
+
+    type Value<'a> = DatString<'a>;
+
+    fn parse<'a>(
+        data: &'a [u8],
+        idx: usize,
+        var_data: VarDataReader<'a>,
+    ) -> Result<DatString<'a>, ParseError> {
+        var_data.get_string_from(data, idx)
+    }
+}
+
+/// Reference to a row of the same table `T`, `None` for null references.
+pub struct Row<T>(PhantomData<T>);
+
+impl<T: super::Row> Column for Row<T> {
+    const SIZE: usize = 8;
+
+    type Value<'a> = Option<ForeignKey<T>>;
+
+    fn parse<'a>(
+        data: &'a [u8],
+        idx: usize,
+        _: VarDataReader<'a>,
+    ) -> Result<Self::Value<'a>, ParseError> {
+        parse_u64(data, idx).map(ForeignKey::new)
+    }
+}
+
+/// Reference to a row of another table `T`, `None` for null references.
+pub struct ForeignRow<T>(PhantomData<T>);
+
+impl<T: super::Row> Column for ForeignRow<T> {
+    const SIZE: usize = 16;
+
+    type Value<'a> = Option<ForeignKey<T>>;
+
+    fn parse<'a>(
+        data: &'a [u8],
+        idx: usize,
+        _: VarDataReader<'a>,
+    ) -> Result<Self::Value<'a>, ParseError> {
+        parse_u64(data, idx).map(ForeignKey::new)
+    }
+}
+
+/// List of `C` in the variable data.
+pub struct List<C>(PhantomData<C>);
+
+impl<C: Column> Column for List<C> {
+    const SIZE: usize = 16;
+
+    type Value<'a> = DatList<'a, C>;
+
+    fn parse<'a>(
+        data: &'a [u8],
+        idx: usize,
+        var_data: VarDataReader<'a>,
+    ) -> Result<Self::Value<'a>, ParseError> {
+        var_data.get_list_from(data, idx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Variable data with the list `[1, 2, 3]` at 8, the string offsets `[44, 54, 99]`
+    /// at 20 and the strings "Fire" at 44 and "Ice" at 54.
+    fn var_data() -> Vec<u8> {
+        let mut data = vec![0xbb; 8];
+        data.extend([1u32, 2, 3].iter().flat_map(|v| v.to_le_bytes()));
+        data.extend([44u64, 54, 99].iter().flat_map(|v| v.to_le_bytes()));
+        for s in ["Fire", "Ice"] {
+            data.extend(s.encode_utf16().chain([0]).flat_map(u16::to_le_bytes));
+        }
+        data
+    }
+
+    /// Row with a single list column.
+    fn row(count: u64, offset: u64) -> Vec<u8> {
+        [count, offset]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect()
+    }
+
+    fn parse<C: Column>(
+        data: &[u8],
+        count: u64,
+        offset: u64,
+    ) -> Result<DatList<'_, C>, ParseError> {
+        VarDataReader(data).get_list::<C>(count, offset)
+    }
+
+    #[test]
+    fn list() {
+        let data = var_data();
+        let list = parse::<U32>(&data, 3, 8).unwrap();
+
+        assert_eq!(list.len(), 3);
+        assert!(!list.is_empty());
+        assert_eq!(list.get(1).unwrap().unwrap(), 2);
+        assert!(list.get(3).is_none());
+        assert_eq!(list.size_hint(), (3, Some(3)));
+        assert_eq!(list.map(Result::unwrap).collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(format!("{list:?}"), "[Ok(1), Ok(2), Ok(3)]");
+    }
+
+    #[test]
+    fn string_list() {
+        let data = var_data();
+        let list = parse::<String>(&data, 3, 20).unwrap();
+
+        let strings = list
+            .map(|s| {
+                s.and_then(|s| {
+                    std::string::String::try_from(&s).map_err(|_| ParseError::InvalidData)
+                })
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(strings[0].as_ref().unwrap(), "Fire");
+        assert_eq!(strings[1].as_ref().unwrap(), "Ice");
+        // The list is in range, the string it references is not.
+        assert!(matches!(strings[2], Err(ParseError::NotEnoughData)));
+    }
+
+    #[test]
+    fn empty_list() {
+        let data = var_data();
+
+        let list = parse::<U32>(&data, 0, 8).unwrap();
+        assert!(list.is_empty());
+        assert_eq!(list.len(), 0);
+        assert!(list.get(0).is_none());
+        assert_eq!(list.count(), 0);
+
+        // An empty list at the end of the variable data.
+        assert!(parse::<String>(&data, 0, data.len() as u64)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn out_of_range() {
+        let data = var_data();
+        let len = data.len() as u64;
+
+        for (count, offset) in [(1, len), (4, len - 12), (0, len + 1), (1, u64::MAX)] {
+            assert!(
+                matches!(
+                    parse::<U32>(&data, count, offset),
+                    Err(ParseError::NotEnoughData)
+                ),
+                "{count} elements at {offset}"
+            );
+        }
+        assert!(matches!(
+            parse::<String>(&data, 8, 20),
+            Err(ParseError::NotEnoughData)
+        ));
+    }
+
+    #[test]
+    fn overflow() {
+        let data = var_data();
+
+        for count in [u64::MAX, u64::MAX / 4 + 1, u64::MAX / 16 + 1] {
+            assert!(matches!(
+                parse::<List<U32>>(&data, count, 8),
+                Err(ParseError::NotEnoughData)
+            ));
+        }
+        assert!(matches!(
+            parse::<U32>(&data, u64::MAX / 4 + 1, 8),
+            Err(ParseError::NotEnoughData)
+        ));
+    }
+
+    #[test]
+    fn column() {
+        let data = var_data();
+        let row = row(3, 8);
+        let list = List::<U32>::parse(&row, 0, VarDataReader(&data)).unwrap();
+        assert_eq!(list.map(Result::unwrap).collect::<Vec<_>>(), [1, 2, 3]);
+
+        assert!(matches!(
+            List::<U32>::parse(&row[..15], 0, VarDataReader(&data)),
+            Err(ParseError::NotEnoughData)
+        ));
+    }
+}
@@ -0,0 +1,691 @@
+use std::borrow::Cow;
+
+use serde::Deserialize;
+
+use super::{
+    file::VarDataReader,
+    key::NULL_KEY,
+    row::ParseError,
+    utils::{parse_u32, parse_u64},
+    DatError, DatFile, DatString, Row,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum SchemaError {
+    #[error("invalid schema: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Dat(#[from] DatError),
+    #[error("column '{column}' has an unsupported type")]
+    UnsupportedColumn { column: String },
+    #[error("schema row size {schema} does not match the row size {file} of the file")]
+    RowSize { schema: usize, file: usize },
+    #[error("column '{0}' does not exist")]
+    UnknownColumn(String),
+    #[error("failed to parse column '{column}': {source}")]
+    Parse { column: String, source: ParseError },
+}
+
+/// A community DAT schema, the `schema.min.json` of `poe-tool-dev/dat-schema`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Schema {
+    pub tables: Vec<TableSchema>,
+}
+
+impl Schema {
+    pub fn from_json(json: &str) -> Result<Self, SchemaError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Returns the schema of a Path of Exile 1 table.
+    pub fn table(&self, name: &str) -> Option<&TableSchema> {
+        self.tables
+            .iter()
+            .find(|table| table.valid_for & 1 != 0 && table.name.eq_ignore_ascii_case(name))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TableSchema {
+    pub name: String,
+    pub columns: Vec<ColumnSchema>,
+    /// Game the table is valid for, `1` for Path of Exile 1, `2` for Path of Exile 2, `3` for both.
+    #[serde(rename = "validFor", default = "valid_for_all")]
+    pub valid_for: u8,
+}
+
+fn valid_for_all() -> u8 {
+    3
+}
+
+impl TableSchema {
+    /// Path of the table in the bundle.
+    pub fn file_name(&self) -> String {
+        format!("Data/{}.datc64", self.name)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ColumnSchema {
+    /// Name of the column, unknown columns have no name.
+    pub name: Option<String>,
+    #[serde(rename = "type")]
+    pub ty: ColumnType,
+    /// The column is a list of `ty`.
+    #[serde(default)]
+    pub array: bool,
+    /// The column is a pair of `ty`.
+    #[serde(default)]
+    pub interval: bool,
+    /// Table referenced by the keys of the column.
+    pub references: Option<Reference>,
+}
+
+impl ColumnSchema {
+    /// Size of the column in a row of a `.datc64` file.
+    fn size(&self) -> Option<usize> {
+        match (self.array, self.interval) {
+            (true, _) => Some(16),
+            (false, true) => self.ty.size().map(|size| size * 2),
+            (false, false) => self.ty.size(),
+        }
+    }
+
+    fn display_name(&self, index: usize) -> String {
+        self.name.clone().unwrap_or_else(|| format!("#{index}"))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Reference {
+    pub table: String,
+    pub column: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColumnType {
+    Bool,
+    String,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    /// Reference to a row of the same table.
+    Row,
+    /// Reference to a row of another table.
+    ForeignRow,
+    /// Index into an enumeration.
+    EnumRow,
+    /// Element type of a list with unknown contents.
+    Array,
+    #[serde(other)]
+    Unknown,
+}
+
+impl ColumnType {
+    fn size(self) -> Option<usize> {
+        match self {
+            Self::Bool => Some(1),
+            Self::I16 | Self::U16 => Some(2),
+            Self::I32 | Self::U32 | Self::F32 | Self::EnumRow => Some(4),
+            Self::String | Self::Row => Some(8),
+            Self::ForeignRow => Some(16),
+            Self::Array | Self::Unknown => None,
+        }
+    }
+
+    /// Reads a single value, `table` is the table referenced by keys.
+    fn read<'a>(
+        self,
+        table: Option<&'a str>,
+        data: &'a [u8],
+        var_data: VarDataReader<'a>,
+    ) -> Result<Value<'a>, ParseError> {
+        let bytes = |len: usize| data.get(..len).ok_or(ParseError::NotEnoughData);
+
+        Ok(match self {
+            Self::Bool => Value::Bool(bytes(1)?[0] != 0),
+            Self::I16 => Value::I16(i16::from_le_bytes(bytes(2)?.try_into().unwrap())),
+            Self::U16 => Value::U16(u16::from_le_bytes(bytes(2)?.try_into().unwrap())),
+            Self::I32 => Value::I32(parse_u32(data, 0)? as i32),
+            Self::U32 => Value::U32(parse_u32(data, 0)?),
+            Self::F32 => Value::F32(f32::from_bits(parse_u32(data, 0)?)),
+            Self::String => Value::String(var_data.get_string_from(data, 0)?),
+            Self::Row | Self::ForeignRow => {
+                let key = parse_u64(data, 0)?;
+                Value::Key {
+                    table,
+                    index: (key != NULL_KEY).then_some(key),
+                }
+            }
+            Self::EnumRow => Value::EnumRow(parse_u32(data, 0)?),
+            Self::Array | Self::Unknown => return Err(ParseError::InvalidData),
+        })
+    }
+}
+
+/// A value of a column.
+#[derive(Debug, Clone)]
+pub enum Value<'a> {
+    Bool(bool),
+    I16(i16),
+    U16(u16),
+    I32(i32),
+    U32(u32),
+    F32(f32),
+    String(DatString<'a>),
+    /// Row `index` of the referenced `table`, the index is `None` for null references.
+    ///
+    /// The table is `None` if the schema does not specify the referenced table.
+    Key {
+        table: Option<&'a str>,
+        index: Option<u64>,
+    },
+    EnumRow(u32),
+    Interval(Box<(Value<'a>, Value<'a>)>),
+    Array(Vec<Value<'a>>),
+    /// A list with unknown element type and its length.
+    UnknownArray(u64),
+}
+
+impl<'a> Value<'a> {
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Self::Bool(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of integer columns.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Self::I16(v) => Some(v.into()),
+            Self::U16(v) => Some(v.into()),
+            Self::I32(v) => Some(v.into()),
+            Self::U32(v) | Self::EnumRow(v) => Some(v.into()),
+            _ => None,
+        }
+    }
+
+    pub fn as_f32(&self) -> Option<f32> {
+        match *self {
+            Self::F32(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<DatString<'a>> {
+        match *self {
+            Self::String(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Returns the row index of references, `None` for null references and other values.
+    pub fn as_key(&self) -> Option<u64> {
+        match *self {
+            Self::Key { index, .. } => index,
+            _ => None,
+        }
+    }
+
+    /// Returns the table referenced by keys.
+    pub fn referenced_table(&self) -> Option<&'a str> {
+        match *self {
+            Self::Key { table, .. } => table,
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value<'a>]> {
+        match self {
+            Self::Array(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+/// Rows as raw bytes, columns are decoded by [`DynamicRow`].
+struct RawRow;
+
+impl Row for RawRow {
+    const FILE: &'static str = "";
+
+    type Item<'a> = (&'a [u8], VarDataReader<'a>);
+
+    fn parse<'a>(
+        data: &'a [u8],
+        var_data: VarDataReader<'a>,
+    ) -> Result<Self::Item<'a>, ParseError> {
+        Ok((data, var_data))
+    }
+}
+
+/// A DAT file whose rows are decoded by a [`TableSchema`] at runtime.
+pub struct DynamicDatFile<'a> {
+    table: TableSchema,
+    /// Offset of each column in a row.
+    offsets: Vec<usize>,
+    file: DatFile<'a, RawRow>,
+}
+
+impl<'a> DynamicDatFile<'a> {
+    pub fn new(table: TableSchema, data: impl Into<Cow<'a, [u8]>>) -> Result<Self, SchemaError> {
+        let mut offsets = Vec::with_capacity(table.columns.len());
+        let mut row_size = 0;
+        for (i, column) in table.columns.iter().enumerate() {
+            let size = column
+                .size()
+                .ok_or_else(|| SchemaError::UnsupportedColumn {
+                    column: column.display_name(i),
+                })?;
+            offsets.push(row_size);
+            row_size += size;
+        }
+
+        // Empty tables have no rows to determine the row size from.
+        let file = DatFile::try_new(data)?;
+        if !file.is_empty() && file.row_size() != row_size {
+            return Err(SchemaError::RowSize {
+                schema: row_size,
+                file: file.row_size(),
+            });
+        }
+
+        Ok(Self {
+            table,
+            offsets,
+            file,
+        })
+    }
+
+    pub fn table(&self) -> &TableSchema {
+        &self.table
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = DynamicRow<'_>> + '_ {
+        self.file
+            .iter()
+            .map(|(data, var_data)| self.row(data, var_data))
+    }
+
+    pub fn get(&self, index: usize) -> Option<DynamicRow<'_>> {
+        self.file
+            .get(index)
+            .map(|(data, var_data)| self.row(data, var_data))
+    }
+
+    pub fn len(&self) -> usize {
+        self.file.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.file.is_empty()
+    }
+
+    fn row<'b>(&'b self, data: &'b [u8], var_data: VarDataReader<'b>) -> DynamicRow<'b> {
+        DynamicRow {
+            table: &self.table,
+            offsets: &self.offsets,
+            data,
+            var_data,
+        }
+    }
+}
+
+impl<'a> std::fmt::Debug for DynamicDatFile<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DynamicDatFile")
+            .field("table", &self.table.name)
+            .field("row_count", &self.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A row of a [`DynamicDatFile`].
+#[derive(Clone, Copy)]
+pub struct DynamicRow<'a> {
+    table: &'a TableSchema,
+    offsets: &'a [usize],
+    data: &'a [u8],
+    var_data: VarDataReader<'a>,
+}
+
+impl<'a> DynamicRow<'a> {
+    /// Returns the value of the column with the name `column`.
+    pub fn get(&self, column: &str) -> Result<Value<'a>, SchemaError> {
+        let index = self
+            .table
+            .columns
+            .iter()
+            .position(|c| c.name.as_deref() == Some(column))
+            .ok_or_else(|| SchemaError::UnknownColumn(column.to_owned()))?;
+
+        self.value(index).map_err(|source| SchemaError::Parse {
+            column: column.to_owned(),
+            source,
+        })
+    }
+
+    /// Returns the value of the column at `index`.
+    pub fn value(&self, index: usize) -> Result<Value<'a>, ParseError> {
+        let column = self
+            .table
+            .columns
+            .get(index)
+            .ok_or(ParseError::InvalidData)?;
+        let data = self
+            .data
+            .get(self.offsets[index]..)
+            .ok_or(ParseError::NotEnoughData)?;
+        let table = match column.ty {
+            ColumnType::Row => Some(self.table.name.as_str()),
+            _ => column.references.as_ref().map(|r| r.table.as_str()),
+        };
+
+        if column.array {
+            let count = parse_u64(data, 0)?;
+            let offset = parse_u64(data, 8)?;
+
+            let Some(size) = column.ty.size() else {
+                return Ok(Value::UnknownArray(count));
+            };
+
+            let len = usize::try_from(count)
+                .ok()
+                .and_then(|count| count.checked_mul(size))
+                .ok_or(ParseError::NotEnoughData)?;
+            let elements = self.var_data.get(offset, len)?;
+
+            return elements
+                .chunks_exact(size)
+                .map(|element| column.ty.read(table, element, self.var_data))
+                .collect::<Result<_, _>>()
+                .map(Value::Array);
+        }
+
+        let value = column.ty.read(table, data, self.var_data)?;
+        match (column.interval, column.ty.size()) {
+            (true, Some(size)) => {
+                let data = data.get(size..).ok_or(ParseError::NotEnoughData)?;
+                let end = column.ty.read(table, data, self.var_data)?;
+                Ok(Value::Interval(Box::new((value, end))))
+            }
+            _ => Ok(value),
+        }
+    }
+
+    /// All columns of the row with their name.
+    pub fn values(
+        &self,
+    ) -> impl Iterator<Item = (Option<&'a str>, Result<Value<'a>, ParseError>)> + '_ {
+        self.table
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| (column.name.as_deref(), self.value(i)))
+    }
+}
+
+impl<'a> std::fmt::Debug for DynamicRow<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut row = f.debug_map();
+        for (i, (name, value)) in self.values().enumerate() {
+            let name = name.map_or_else(|| format!("#{i}"), ToOwned::to_owned);
+            match value {
+                Ok(value) => row.entry(&name, &value),
+                Err(err) => row.entry(&name, &err),
+            };
+        }
+        row.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: &str = r#"{
+        "version": 5,
+        "tables": [
+            {
+                "name": "Test",
+                "validFor": 2,
+                "columns": []
+            },
+            {
+                "name": "Test",
+                "validFor": 1,
+                "columns": [
+                    { "name": "Id", "type": "string", "array": false, "interval": false, "unique": true },
+                    { "name": "Flag", "type": "bool" },
+                    { "name": "Small", "type": "i16" },
+                    { "name": "Level", "type": "u32" },
+                    { "name": "Ratio", "type": "f32" },
+                    { "name": "Range", "type": "i32", "interval": true },
+                    { "name": "Parent", "type": "row", "references": null },
+                    { "name": "Item", "type": "foreignrow", "references": { "table": "BaseItemTypes" } },
+                    { "name": "Other", "type": "foreignrow" },
+                    { "name": "Tags", "type": "u32", "array": true },
+                    { "name": null, "type": "array", "array": true },
+                    { "name": "Kind", "type": "enumrow" }
+                ]
+            }
+        ]
+    }"#;
+
+    const NULL: [u8; 8] = NULL_KEY.to_le_bytes();
+
+    fn table() -> TableSchema {
+        Schema::from_json(SCHEMA)
+            .unwrap()
+            .table("test")
+            .unwrap()
+            .clone()
+    }
+
+    fn dat(row_count: u32, rows: &[u8], var_data: &[u8]) -> Vec<u8> {
+        let mut data = row_count.to_le_bytes().to_vec();
+        data.extend_from_slice(rows);
+        data.extend_from_slice(&[0xbb; 8]);
+        data.extend_from_slice(var_data);
+        data
+    }
+
+    /// Two rows of the `Test` table, the second row has null keys and empty arrays.
+    fn fixture() -> Vec<u8> {
+        // "Fire" at offset 8 and the tags `[3, 7]` at offset 18.
+        let mut var_data = "Fire"
+            .encode_utf16()
+            .chain([0])
+            .flat_map(u16::to_le_bytes)
+            .collect::<Vec<_>>();
+        var_data.extend([3u32, 7].iter().flat_map(|v| v.to_le_bytes()));
+
+        let mut rows = Vec::new();
+        for row in 0..2u64 {
+            let full = row == 0;
+            rows.extend(8u64.to_le_bytes());
+            rows.push(u8::from(full));
+            rows.extend((-2i16).to_le_bytes());
+            rows.extend(70u32.to_le_bytes());
+            rows.extend(1.5f32.to_le_bytes());
+            rows.extend((-1i32).to_le_bytes());
+            rows.extend(10i32.to_le_bytes());
+            rows.extend(if full { 1u64.to_le_bytes() } else { NULL });
+            rows.extend(if full { 5u64.to_le_bytes() } else { NULL });
+            rows.extend(NULL);
+            rows.extend(NULL);
+            rows.extend(NULL);
+            rows.extend(if full { 2u64 } else { 0 }.to_le_bytes());
+            rows.extend(18u64.to_le_bytes());
+            rows.extend(if full { 3u64 } else { 0 }.to_le_bytes());
+            rows.extend(0u64.to_le_bytes());
+            rows.extend((row as u32 + 1).to_le_bytes());
+        }
+
+        dat(2, &rows, &var_data)
+    }
+
+    #[test]
+    fn schema() {
+        let schema = Schema::from_json(SCHEMA).unwrap();
+        let table = schema.table("TEST").unwrap();
+        assert_eq!(table.valid_for, 1);
+        assert_eq!(table.file_name(), "Data/Test.datc64");
+        assert_eq!(table.columns.len(), 12);
+        assert!(schema.table("Missing").is_none());
+
+        assert!(Schema::from_json("{}").is_err());
+    }
+
+    #[test]
+    fn scalars() {
+        let file = DynamicDatFile::new(table(), fixture()).unwrap();
+        assert_eq!(file.len(), 2);
+
+        let row = file.get(0).unwrap();
+        let id = row.get("Id").unwrap().as_str().unwrap();
+        assert_eq!(String::try_from(&id).unwrap(), "Fire");
+        assert_eq!(row.get("Flag").unwrap().as_bool(), Some(true));
+        assert_eq!(row.get("Small").unwrap().as_i64(), Some(-2));
+        assert_eq!(row.get("Level").unwrap().as_i64(), Some(70));
+        assert_eq!(row.get("Ratio").unwrap().as_f32(), Some(1.5));
+        assert_eq!(row.get("Kind").unwrap().as_i64(), Some(1));
+
+        let row = file.get(1).unwrap();
+        assert_eq!(row.get("Flag").unwrap().as_bool(), Some(false));
+        assert_eq!(row.get("Kind").unwrap().as_i64(), Some(2));
+        assert!(file.get(2).is_none());
+    }
+
+    #[test]
+    fn intervals() {
+        let file = DynamicDatFile::new(table(), fixture()).unwrap();
+        let Value::Interval(interval) = file.get(0).unwrap().get("Range").unwrap() else {
+            panic!("not an interval");
+        };
+        assert_eq!(interval.0.as_i64(), Some(-1));
+        assert_eq!(interval.1.as_i64(), Some(10));
+    }
+
+    #[test]
+    fn keys() {
+        let file = DynamicDatFile::new(table(), fixture()).unwrap();
+
+        let row = file.get(0).unwrap();
+        let parent = row.get("Parent").unwrap();
+        assert_eq!(parent.as_key(), Some(1));
+        assert_eq!(parent.referenced_table(), Some("Test"));
+        let item = row.get("Item").unwrap();
+        assert_eq!(item.as_key(), Some(5));
+        assert_eq!(item.referenced_table(), Some("BaseItemTypes"));
+        assert!(matches!(
+            row.get("Other").unwrap(),
+            Value::Key {
+                table: None,
+                index: None
+            }
+        ));
+
+        let row = file.get(1).unwrap();
+        assert_eq!(row.get("Parent").unwrap().as_key(), None);
+        assert_eq!(row.get("Parent").unwrap().referenced_table(), Some("Test"));
+        assert_eq!(row.get("Item").unwrap().as_key(), None);
+    }
+
+    #[test]
+    fn arrays() {
+        let file = DynamicDatFile::new(table(), fixture()).unwrap();
+
+        let row = file.get(0).unwrap();
+        let tags = row.get("Tags").unwrap();
+        let tags = tags.as_array().unwrap();
+        assert_eq!(
+            tags.iter().map(|t| t.as_i64().unwrap()).collect::<Vec<_>>(),
+            [3, 7]
+        );
+        assert!(matches!(row.value(10), Ok(Value::UnknownArray(3))));
+
+        let row = file.get(1).unwrap();
+        assert_eq!(row.get("Tags").unwrap().as_array().unwrap().len(), 0);
+        assert!(matches!(row.value(10), Ok(Value::UnknownArray(0))));
+    }
+
+    #[test]
+    fn array_out_of_bounds() {
+        let mut data = fixture();
+        // Moves the tags of the first row past the end of the variable data.
+        let tags_offset = 4 + 8 + 1 + 2 + 4 + 4 + 8 + 8 + 16 + 16 + 8;
+        data[tags_offset..tags_offset + 8].copy_from_slice(&22u64.to_le_bytes());
+
+        let file = DynamicDatFile::new(table(), data).unwrap();
+        let err = file.get(0).unwrap().get("Tags").unwrap_err();
+        assert!(matches!(
+            err,
+            SchemaError::Parse {
+                source: ParseError::NotEnoughData,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn values() {
+        let file = DynamicDatFile::new(table(), fixture()).unwrap();
+        let row = file.get(0).unwrap();
+
+        let names = row.values().map(|(name, _)| name).collect::<Vec<_>>();
+        assert_eq!(names[0], Some("Id"));
+        assert_eq!(names[10], None);
+        assert!(row.values().all(|(_, value)| value.is_ok()));
+        assert!(row.value(12).is_err());
+        assert!(matches!(
+            row.get("Missing"),
+            Err(SchemaError::UnknownColumn(_))
+        ));
+
+        let debug = format!("{row:?}");
+        assert!(debug.contains("\"Level\": U32(70)"), "{debug}");
+        assert!(debug.contains("\"#10\": UnknownArray(3)"), "{debug}");
+    }
+
+    #[test]
+    fn empty_table() {
+        let file = DynamicDatFile::new(table(), dat(0, &[], &[])).unwrap();
+        assert!(file.is_empty());
+        assert_eq!(file.iter().count(), 0);
+    }
+
+    #[test]
+    fn row_size_mismatch() {
+        let mut table = table();
+        table.columns.pop();
+
+        let err = DynamicDatFile::new(table, fixture()).unwrap_err();
+        assert!(matches!(
+            err,
+            SchemaError::RowSize {
+                schema: 99,
+                file: 103
+            }
+        ));
+    }
+
+    #[test]
+    fn unsupported_column() {
+        let mut table = table();
+        table.columns[1].ty = ColumnType::Unknown;
+
+        let err = DynamicDatFile::new(table, fixture()).unwrap_err();
+        assert!(matches!(err, SchemaError::UnsupportedColumn { column } if column == "Flag"));
+    }
+
+    #[test]
+    fn malformed_file() {
+        let err = DynamicDatFile::new(table(), vec![1, 0]).unwrap_err();
+        assert!(matches!(err, SchemaError::Dat(DatError::TruncatedHeader)));
+    }
+}
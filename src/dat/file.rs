@@ -1,11 +1,12 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, marker::PhantomData};
 
-use super::{row::ParseError, utils::parse_u64, Row};
+use super::{column::Column, row::ParseError, utils::parse_u64, Row};
 
 const VDATA_MAGIC: &[u8] = &[0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb];
 
+/// Reader for the variable data section of a DAT file, which contains strings and lists.
 #[derive(Copy, Clone)]
-pub struct VarDataReader<'a>(&'a [u8]);
+pub struct VarDataReader<'a>(pub(crate) &'a [u8]);
 
 impl<'a> VarDataReader<'a> {
     pub fn get_string_from(&self, data: &[u8], idx: usize) -> Result<DatString<'a>, ParseError> {
@@ -13,18 +14,113 @@ impl<'a> VarDataReader<'a> {
         self.get_string(loc)
     }
 
+    /// Reads the list column at `idx` of the row `data`, a count followed by an offset.
+    pub fn get_list_from<C: Column>(
+        &self,
+        data: &[u8],
+        idx: usize,
+    ) -> Result<DatList<'a, C>, ParseError> {
+        let count = parse_u64(data, idx)?;
+        let offset = parse_u64(data, idx + 8)?;
+        self.get_list(count, offset)
+    }
+
+    /// Returns the list of `count` elements of the column type `C` starting at `offset`.
+    pub fn get_list<C: Column>(
+        &self,
+        count: u64,
+        offset: u64,
+    ) -> Result<DatList<'a, C>, ParseError> {
+        let len = usize::try_from(count)
+            .ok()
+            .and_then(|count| count.checked_mul(C::SIZE))
+            .ok_or(ParseError::NotEnoughData)?;
+
+        Ok(DatList {
+            data: self.get(offset, len)?,
+            var_data: *self,
+            _column: PhantomData,
+        })
+    }
+
+    /// Returns `len` bytes of the variable data starting at `offset`.
+    pub(super) fn get(&self, offset: u64, len: usize) -> Result<&'a [u8], ParseError> {
+        usize::try_from(offset)
+            .ok()
+            .and_then(|offset| self.0.get(offset..offset.checked_add(len)?))
+            .ok_or(ParseError::NotEnoughData)
+    }
+
+    /// Returns the null terminated UTF-16 string starting at `offset`, strings without a
+    /// terminator end with the variable data.
     pub fn get_string(&self, offset: u64) -> Result<DatString<'a>, ParseError> {
-        let offset = offset as usize;
-        let idx = self.0[offset..]
+        let data = usize::try_from(offset)
+            .ok()
+            .and_then(|offset| self.0.get(offset..))
+            .ok_or(ParseError::NotEnoughData)?;
+        let len = data
             .chunks_exact(2)
             .position(|a| a == [0, 0])
-            .map(|idx| idx * 2)
-            .unwrap_or(self.0.len());
-        let data = self
-            .0
-            .get(offset..offset + idx)
-            .ok_or(ParseError::NotEnoughData)?;
-        Ok(DatString(data))
+            .map_or(data.len(), |idx| idx * 2);
+        Ok(DatString(&data[..len]))
+    }
+}
+
+/// List of the column type `C` in the variable data, elements are parsed on access.
+pub struct DatList<'a, C> {
+    data: &'a [u8],
+    var_data: VarDataReader<'a>,
+    _column: PhantomData<C>,
+}
+
+impl<'a, C: Column> DatList<'a, C> {
+    pub fn len(&self) -> usize {
+        self.data.len() / C::SIZE
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<Result<C::Value<'a>, ParseError>> {
+        (index < self.len()).then(|| C::parse(self.data, index * C::SIZE, self.var_data))
+    }
+}
+
+impl<'a, C: Column> Iterator for DatList<'a, C> {
+    type Item = Result<C::Value<'a>, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        let item = C::parse(self.data, 0, self.var_data);
+        self.data = &self.data[C::SIZE..];
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len(), Some(self.len()))
+    }
+}
+
+impl<'a, C: Column> ExactSizeIterator for DatList<'a, C> {}
+
+impl<'a, C> Clone for DatList<'a, C> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, C> Copy for DatList<'a, C> {}
+
+impl<'a, C: Column> std::fmt::Debug for DatList<'a, C>
+where
+    C::Value<'a>: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(*self).finish()
     }
 }
 
@@ -86,6 +182,16 @@ impl<'a> std::fmt::Debug for DatString<'a> {
     }
 }
 
+#[derive(Debug, thiserror::Error)]
+pub enum DatError {
+    #[error("file is too short to contain a header")]
+    TruncatedHeader,
+    #[error("missing the boundary of the variable data")]
+    MissingBoundary,
+    #[error("{size} bytes of row data can not be split into {row_count} rows")]
+    RowSize { size: usize, row_count: usize },
+}
+
 pub struct DatFile<'a, R: Row> {
     pub row_count: usize,
     row_size: usize,
@@ -95,49 +201,101 @@ pub struct DatFile<'a, R: Row> {
 }
 
 impl<'a, R: Row> DatFile<'a, R> {
+    /// Parses the layout of a DAT file.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the file is malformed, see [`DatFile::try_new`].
     pub fn new(data: impl Into<Cow<'a, [u8]>>) -> Self {
+        match Self::try_new(data) {
+            Ok(file) => file,
+            Err(err) => panic!("invalid '{}': {err}", R::FILE),
+        }
+    }
+
+    /// Parses the layout of a DAT file, rows are only parsed on access.
+    pub fn try_new(data: impl Into<Cow<'a, [u8]>>) -> Result<Self, DatError> {
         let data = data.into();
-        // TODO: this can panic
-        let row_count = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
+        let header = data.get(..4).ok_or(DatError::TruncatedHeader)?;
+        let row_count = u32::from_le_bytes(header.try_into().unwrap()) as usize;
 
-        // TODO: errors
-        let boundary = data
+        let boundary = data[4..]
             .windows(VDATA_MAGIC.len())
             .position(|window| window == VDATA_MAGIC)
-            .expect("magic");
+            .map(|position| position + 4)
+            .ok_or(DatError::MissingBoundary)?;
 
-        let row_size = (boundary - 4) / row_count;
+        let size = boundary - 4;
+        let row_size = match row_count {
+            0 if size == 0 => 0,
+            0 => return Err(DatError::RowSize { size, row_count }),
+            _ if size % row_count != 0 => return Err(DatError::RowSize { size, row_count }),
+            _ => size / row_count,
+        };
 
-        Self {
+        Ok(Self {
             row_count,
             row_size,
             data,
             boundary,
             _row: Default::default(),
-        }
+        })
     }
 
+    /// Iterates all rows.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a row fails to parse, see [`DatFile::try_iter`].
     pub fn iter(&self) -> impl Iterator<Item = R::Item<'_>> + '_ {
-        let vdr = VarDataReader(&self.data[self.boundary..]);
-        self.data[4..]
-            .chunks_exact(self.row_size)
+        self.try_iter()
+            .map(|row| row.unwrap_or_else(|err| panic!("{err}")))
+    }
+
+    /// Iterates all rows, a row which fails to parse does not end the iteration.
+    pub fn try_iter(&self) -> impl Iterator<Item = Result<R::Item<'_>, ParseError>> + '_ {
+        let vdr = self.var_data();
+        self.data[4..self.boundary]
+            .chunks_exact(self.row_size.max(1))
             .take(self.row_count)
-            .map(move |row| R::parse(row, vdr).unwrap()) // TODO: unwrap
+            .enumerate()
+            .map(move |(index, row)| R::parse(row, vdr).map_err(|err| err.in_row(index)))
     }
 
+    /// Returns the row at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the row fails to parse, see [`DatFile::try_get`].
     pub fn get(&self, index: usize) -> Option<R::Item<'_>> {
+        self.try_get(index)
+            .map(|row| row.unwrap_or_else(|err| panic!("{err}")))
+    }
+
+    /// Returns the row at `index`, `None` if the row does not exist.
+    pub fn try_get(&self, index: usize) -> Option<Result<R::Item<'_>, ParseError>> {
+        if index >= self.row_count {
+            return None;
+        }
+
         let start = 4 + index * self.row_size;
-        let end = start + self.row_size;
-        let vdr = VarDataReader(&self.data[self.boundary..]);
-        self.data
-            .get(start..end)
-            .map(|row| R::parse(row, vdr).unwrap()) // TODO: unwrap
+        let row = &self.data[start..start + self.row_size];
+        Some(R::parse(row, self.var_data()).map_err(|err| err.in_row(index)))
+    }
+
+    fn var_data(&self) -> VarDataReader<'_> {
+        VarDataReader(&self.data[self.boundary..])
     }
 
     pub fn len(&self) -> usize {
         self.row_count
     }
 
+    /// Size of a single row in bytes.
+    pub fn row_size(&self) -> usize {
+        self.row_size
+    }
+
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
@@ -150,3 +308,159 @@ impl<'a, R: Row> std::fmt::Debug for DatFile<'a, R> {
             .finish_non_exhaustive()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dat::utils::parse_u32;
+
+    /// Rows of a single `u32` column, `u32::MAX` fails to parse.
+    struct Numbers;
+
+    impl Row for Numbers {
+        const FILE: &'static str = "Data/Numbers.datc64";
+
+        type Item<'a> = u32;
+
+        fn parse<'a>(data: &'a [u8], _: VarDataReader<'a>) -> Result<u32, ParseError> {
+            match parse_u32(data, 0)? {
+                u32::MAX => Err(ParseError::InvalidData),
+                n => Ok(n),
+            }
+        }
+    }
+
+    fn numbers(values: &[u32]) -> Vec<u8> {
+        let mut data = (values.len() as u32).to_le_bytes().to_vec();
+        data.extend(values.iter().flat_map(|v| v.to_le_bytes()));
+        data.extend(VDATA_MAGIC);
+        data
+    }
+
+    fn utf16(s: &str) -> Vec<u8> {
+        s.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    fn string(s: Result<DatString, ParseError>) -> String {
+        String::try_from(&s.unwrap()).unwrap()
+    }
+
+    #[test]
+    fn strings() {
+        let mut data = utf16("Fire");
+        data.extend([0, 0]);
+        data.extend(utf16("Ice"));
+        let var_data = VarDataReader(&data);
+
+        assert_eq!(string(var_data.get_string(0)), "Fire");
+        assert_eq!(string(var_data.get_string(2)), "ire");
+        assert_eq!(string(var_data.get_string(8)), "");
+        // Without a terminator the string ends with the variable data.
+        assert_eq!(string(var_data.get_string(10)), "Ice");
+        assert_eq!(string(var_data.get_string(14)), "e");
+        assert_eq!(string(var_data.get_string(16)), "");
+    }
+
+    #[test]
+    fn strings_out_of_bounds() {
+        let data = utf16("Fire");
+        let var_data = VarDataReader(&data);
+
+        assert!(matches!(
+            var_data.get_string(9),
+            Err(ParseError::NotEnoughData)
+        ));
+        assert!(matches!(
+            var_data.get_string(u64::MAX),
+            Err(ParseError::NotEnoughData)
+        ));
+        assert!(matches!(
+            var_data.get_string_from(&[0; 7], 0),
+            Err(ParseError::NotEnoughData)
+        ));
+    }
+
+    #[test]
+    fn rows() {
+        let file = DatFile::<Numbers>::try_new(numbers(&[1, 2, 3])).unwrap();
+        assert_eq!(file.len(), 3);
+        assert_eq!(file.row_size(), 4);
+        assert_eq!(file.iter().collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(file.get(2), Some(3));
+        assert!(file.try_get(3).is_none());
+    }
+
+    #[test]
+    fn truncated_header() {
+        for len in 0..4 {
+            let err = DatFile::<Numbers>::try_new(vec![0; len]).unwrap_err();
+            assert!(matches!(err, DatError::TruncatedHeader));
+        }
+    }
+
+    #[test]
+    fn missing_boundary() {
+        let mut data = numbers(&[1]);
+        data.truncate(data.len() - 1);
+        let err = DatFile::<Numbers>::try_new(data).unwrap_err();
+        assert!(matches!(err, DatError::MissingBoundary));
+    }
+
+    #[test]
+    fn row_size_mismatch() {
+        let mut data = numbers(&[1, 2]);
+        data.insert(4, 0);
+        let err = DatFile::<Numbers>::try_new(data).unwrap_err();
+        assert!(matches!(
+            err,
+            DatError::RowSize {
+                size: 9,
+                row_count: 2
+            }
+        ));
+    }
+
+    #[test]
+    fn no_rows() {
+        let file = DatFile::<Numbers>::try_new(numbers(&[])).unwrap();
+        assert!(file.is_empty());
+        assert_eq!(file.iter().count(), 0);
+        assert!(file.try_get(0).is_none());
+
+        // Row data without rows.
+        let mut data = numbers(&[1]);
+        data[0] = 0;
+        let err = DatFile::<Numbers>::try_new(data).unwrap_err();
+        assert!(matches!(
+            err,
+            DatError::RowSize {
+                size: 4,
+                row_count: 0
+            }
+        ));
+    }
+
+    #[test]
+    fn errors_report_the_row() {
+        let file = DatFile::<Numbers>::try_new(numbers(&[1, u32::MAX, 3])).unwrap();
+
+        let err = file.try_get(1).unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "row 1: invalid data");
+        assert!(matches!(
+            err,
+            ParseError::Row { index: 1, source } if matches!(*source, ParseError::InvalidData)
+        ));
+
+        let rows = file.try_iter().collect::<Vec<_>>();
+        assert_eq!(rows.len(), 3);
+        assert!(matches!(rows[1], Err(ParseError::Row { index: 1, .. })));
+        assert_eq!(rows[2].as_ref().unwrap(), &3);
+    }
+
+    #[test]
+    #[should_panic(expected = "row 1: invalid data")]
+    fn iter_panics_on_errors() {
+        let file = DatFile::<Numbers>::try_new(numbers(&[1, u32::MAX])).unwrap();
+        file.iter().for_each(drop);
+    }
+}
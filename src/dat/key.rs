@@ -0,0 +1,78 @@
+use std::marker::PhantomData;
+
+use super::{DatFile, ParseError, Row};
+
+/// Key of a null reference.
+pub(crate) const NULL_KEY: u64 = 0xfefe_fefe_fefe_fefe;
+
+#[derive(Debug, thiserror::Error)]
+pub enum KeyError {
+    #[error("row {index} does not exist in '{file}'")]
+    MissingRow { file: &'static str, index: u64 },
+    #[error("failed to parse '{file}': {source}")]
+    Parse {
+        file: &'static str,
+        source: ParseError,
+    },
+}
+
+/// Reference to a row of the table `T`.
+///
+/// Tables with a lifetime are referenced with a `'static` lifetime, e.g.
+/// `ForeignKey<Words<'static>>`.
+pub struct ForeignKey<T> {
+    index: u64,
+    _row: PhantomData<fn() -> T>,
+}
+
+impl<T: Row> ForeignKey<T> {
+    /// Creates a key from its raw value, `None` for the null sentinel.
+    pub fn new(key: u64) -> Option<Self> {
+        (key != NULL_KEY).then_some(Self {
+            index: key,
+            _row: PhantomData,
+        })
+    }
+
+    /// Index of the referenced row.
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    /// Returns the referenced row from the loaded table.
+    pub fn resolve<'a>(&self, file: &'a DatFile<'_, T>) -> Result<T::Item<'a>, KeyError> {
+        usize::try_from(self.index)
+            .ok()
+            .and_then(|index| file.try_get(index))
+            .ok_or(KeyError::MissingRow {
+                file: T::FILE,
+                index: self.index,
+            })?
+            .map_err(|source| KeyError::Parse {
+                file: T::FILE,
+                source,
+            })
+    }
+}
+
+impl<T> Clone for ForeignKey<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for ForeignKey<T> {}
+
+impl<T> PartialEq for ForeignKey<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<T> Eq for ForeignKey<T> {}
+
+impl<T> std::fmt::Debug for ForeignKey<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ForeignKey({})", self.index)
+    }
+}
@@ -1,9 +1,16 @@
+pub mod column;
+#[cfg(feature = "dat-schema")]
+mod dynamic;
 mod file;
+mod key;
 mod row;
 mod tables;
 mod utils;
 
-pub(crate) use self::file::VarDataReader;
-pub use self::file::{DatFile, DatString};
-pub(crate) use self::row::Row;
+pub use self::column::Column;
+#[cfg(feature = "dat-schema")]
+pub use self::dynamic::*;
+pub use self::file::{DatError, DatFile, DatList, DatString, VarDataReader};
+pub use self::key::{ForeignKey, KeyError};
+pub use self::row::{ParseError, Row};
 pub use self::tables::*;
@@ -7,6 +7,21 @@ pub enum ParseError {
     NotEnoughData,
     #[error("invalid data")]
     InvalidData,
+    #[error("row {index}: {source}")]
+    Row {
+        index: usize,
+        source: Box<ParseError>,
+    },
+}
+
+impl ParseError {
+    /// Adds the index of the row which failed to parse.
+    pub(crate) fn in_row(self, index: usize) -> Self {
+        Self::Row {
+            index,
+            source: Box::new(self),
+        }
+    }
 }
 
 pub trait Row {
@@ -21,9 +36,9 @@ pub trait Row {
 impl<T: Row> BundleFile for T {
     const NAME: &'static str = T::FILE;
 
-    type Output = super::DatFile<'static, Self>;
+    type Output = Result<super::DatFile<'static, Self>, super::DatError>;
 
     fn from(data: Vec<u8>) -> Self::Output {
-        Self::Output::new(std::borrow::Cow::Owned(data))
+        super::DatFile::try_new(std::borrow::Cow::Owned(data))
     }
 }
@@ -1,122 +1,64 @@
-use super::{row::ParseError, utils::parse_u64, DatString, Row, VarDataReader};
-use crate::dat::utils::parse_u32;
+use super::{
+    column::Column, row::ParseError, utils::parse_u32, DatString, ForeignKey, VarDataReader,
+};
+use crate::Row;
 
-#[derive(Debug)]
+#[derive(Debug, Row)]
+#[row(file = "Data/BaseItemTypes.datc64")]
 pub struct BaseItemTypes<'a> {
+    #[column(string)]
     pub id: DatString<'a>,
+    #[column(string, skip = 24)]
     pub name: DatString<'a>,
+    #[column(u32, skip = 8)]
     pub drop_level: u32,
+    #[column(u32, skip = 72)]
     pub site_visibility: u32,
-    pub item_visual_identity: u64,
-}
-
-impl<'ty> Row for BaseItemTypes<'ty> {
-    const FILE: &'static str = "Data/BaseItemTypes.datc64";
-
-    type Item<'a> = BaseItemTypes<'a>;
-
-    fn parse<'a>(
-        data: &'a [u8],
-        var_data: VarDataReader<'a>,
-    ) -> Result<Self::Item<'a>, ParseError> {
-        let id = var_data.get_string_from(data, 0)?;
-        let name = var_data.get_string_from(data, 32)?;
-        let drop_level = parse_u32(data, 48)?;
-        let site_visibility = parse_u32(data, 124)?;
-        let item_visual_identity = parse_u64(data, 128)?;
-
-        Ok(BaseItemTypes {
-            id,
-            name,
-            drop_level,
-            site_visibility,
-            item_visual_identity,
-        })
-    }
+    #[column(foreign_row(ItemVisualIdentity))]
+    pub item_visual_identity: Option<ForeignKey<ItemVisualIdentity<'static>>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Row)]
+#[row(file = "Data/ItemVisualIdentity.datc64")]
 pub struct ItemVisualIdentity<'a> {
+    #[column(string)]
     pub id: DatString<'a>,
+    #[column(string)]
     pub dds_file: DatString<'a>,
+    #[column(bool, skip = 284)]
     pub is_alternate_art: bool,
 }
 
-impl<'ty> Row for ItemVisualIdentity<'ty> {
-    const FILE: &'static str = "Data/ItemVisualIdentity.datc64";
-
-    type Item<'a> = ItemVisualIdentity<'a>;
-
-    fn parse<'a>(
-        data: &'a [u8],
-        var_data: VarDataReader<'a>,
-    ) -> Result<Self::Item<'a>, ParseError> {
-        let id = var_data.get_string_from(data, 0)?;
-        let dds_file = var_data.get_string_from(data, 8)?;
-        let is_alternate_art = data[300] == 1;
-
-        Ok(ItemVisualIdentity {
-            id,
-            dds_file,
-            is_alternate_art,
-        })
-    }
-}
-
-#[derive(Debug)]
+#[derive(Debug, Row)]
+#[row(file = "Data/UniqueStashLayout.datc64")]
 pub struct UniqueStashLayout {
-    pub words: u64,
-    pub item_visual_identity: u64,
+    #[column(foreign_row(Words))]
+    pub words: Option<ForeignKey<Words<'static>>>,
+    #[column(foreign_row(ItemVisualIdentity))]
+    pub item_visual_identity: Option<ForeignKey<ItemVisualIdentity<'static>>>,
+    #[column(bool, skip = 32)]
     pub show_if_empty_challenge_league: bool,
 }
 
-impl Row for UniqueStashLayout {
-    const FILE: &'static str = "Data/UniqueStashLayout.datc64";
-
-    type Item<'a> = UniqueStashLayout;
-
-    fn parse<'a>(
-        data: &'a [u8],
-        _var_data: VarDataReader<'a>,
-    ) -> Result<Self::Item<'a>, ParseError> {
-        let words = parse_u64(data, 0)?;
-        let item_visual_identity = parse_u64(data, 16)?;
-        let show_if_empty_challenge_league = data[64] == 1;
-
-        Ok(UniqueStashLayout {
-            words,
-            item_visual_identity,
-            show_if_empty_challenge_league,
-        })
-    }
-}
-
-#[derive(Debug)]
+#[derive(Debug, Row)]
+#[row(file = "Data/Words.datc64")]
 pub struct Words<'a> {
+    #[column(string, skip = 48)]
     pub text2: DatString<'a>,
 }
 
-impl<'ty> Row for Words<'ty> {
-    const FILE: &'static str = "Data/Words.datc64";
-
-    type Item<'a> = Words<'a>;
-
-    fn parse<'a>(
-        data: &'a [u8],
-        var_data: VarDataReader<'a>,
-    ) -> Result<Self::Item<'a>, ParseError> {
-        let text2 = var_data.get_string_from(data, 48)?;
-
-        Ok(Words { text2 })
-    }
-}
-
-#[derive(Debug)]
+#[derive(Debug, Row)]
+#[row(file = "Data/SkillGems.datc64")]
 pub struct SkillGems {
-    pub base_item_type: u64,
+    #[column(foreign_row(BaseItemTypes))]
+    pub base_item_type: Option<ForeignKey<BaseItemTypes<'static>>>,
+    #[column(u32, skip = 16)]
     pub str: u32,
+    #[column(u32)]
     pub dex: u32,
+    #[column(u32)]
     pub int: u32,
+    #[column(Color, skip = 39)]
     pub color: Color,
 }
 
@@ -139,33 +81,135 @@ impl Color {
     }
 }
 
-impl Row for SkillGems {
-    const FILE: &'static str = "Data/SkillGems.datc64";
-
-    type Item<'a> = SkillGems;
-
-    fn parse<'a>(
-        data: &'a [u8],
-        _var_data: VarDataReader<'a>,
-    ) -> Result<Self::Item<'a>, ParseError> {
-        let base_item_type = parse_u64(data, 0)?;
-        let str = parse_u32(data, 32)?;
-        let dex = parse_u32(data, 36)?;
-        let int = parse_u32(data, 40)?;
-        let color = match parse_u32(data, 83)? {
-            1 => Color::Red,
-            2 => Color::Green,
-            3 => Color::Blue,
-            4 => Color::White,
-            _ => return Err(ParseError::InvalidData),
-        };
-
-        Ok(SkillGems {
-            base_item_type,
-            str,
-            dex,
-            int,
-            color,
-        })
+impl Column for Color {
+    const SIZE: usize = 4;
+
+    type Value<'a> = Color;
+
+    fn parse<'a>(data: &'a [u8], idx: usize, _: VarDataReader<'a>) -> Result<Color, ParseError> {
+        match parse_u32(data, idx)? {
+            1 => Ok(Color::Red),
+            2 => Ok(Color::Green),
+            3 => Ok(Color::Blue),
+            4 => Ok(Color::White),
+            _ => Err(ParseError::InvalidData),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dat::{key::NULL_KEY, DatFile};
+
+    /// Variable data with the strings `"A"` at 8 and `"B"` at 12 followed by the
+    /// keys `[4, null]` at 16.
+    fn var_data() -> Vec<u8> {
+        let mut data = vec![0xbb; 8];
+        data.extend([b'A', 0, 0, 0, b'B', 0, 0, 0]);
+        for key in [4, NULL_KEY] {
+            data.extend(key.to_le_bytes());
+            data.extend([0; 8]);
+        }
+        data
+    }
+
+    /// Single row table of `size` bytes with the `values` written at their offset.
+    fn dat(size: usize, values: &[(usize, &[u8])]) -> Vec<u8> {
+        let mut row = vec![0; size];
+        for &(offset, value) in values {
+            row[offset..offset + value.len()].copy_from_slice(value);
+        }
+
+        let mut data = 1u32.to_le_bytes().to_vec();
+        data.extend(row);
+        data.extend(var_data());
+        data
+    }
+
+    fn string(s: &DatString) -> String {
+        String::try_from(s).unwrap()
+    }
+
+    const A: &[u8] = &8u64.to_le_bytes();
+    const B: &[u8] = &12u64.to_le_bytes();
+    const NULL: &[u8] = &NULL_KEY.to_le_bytes();
+
+    #[test]
+    fn base_item_types() {
+        let data = dat(
+            144,
+            &[
+                (0, A),
+                (32, B),
+                (48, &68u32.to_le_bytes()),
+                (124, &1u32.to_le_bytes()),
+                (128, &7u64.to_le_bytes()),
+            ],
+        );
+        let file = DatFile::<BaseItemTypes>::try_new(data).unwrap();
+        let row = file.get(0).unwrap();
+
+        assert_eq!(string(&row.id), "A");
+        assert_eq!(string(&row.name), "B");
+        assert_eq!(row.drop_level, 68);
+        assert_eq!(row.site_visibility, 1);
+        assert_eq!(row.item_visual_identity.unwrap().index(), 7);
+    }
+
+    #[test]
+    fn item_visual_identity() {
+        let data = dat(301, &[(0, A), (8, B), (300, &[1])]);
+        let file = DatFile::<ItemVisualIdentity>::try_new(data).unwrap();
+        let row = file.get(0).unwrap();
+
+        assert_eq!(string(&row.id), "A");
+        assert_eq!(string(&row.dds_file), "B");
+        assert!(row.is_alternate_art);
+    }
+
+    #[test]
+    fn unique_stash_layout() {
+        let data = dat(65, &[(0, &3u64.to_le_bytes()), (16, NULL), (64, &[1])]);
+        let file = DatFile::<UniqueStashLayout>::try_new(data).unwrap();
+        let row = file.get(0).unwrap();
+
+        assert_eq!(row.words.unwrap().index(), 3);
+        assert!(row.item_visual_identity.is_none());
+        assert!(row.show_if_empty_challenge_league);
+    }
+
+    #[test]
+    fn words() {
+        let data = dat(56, &[(48, B)]);
+        let file = DatFile::<Words>::try_new(data).unwrap();
+        assert_eq!(string(&file.get(0).unwrap().text2), "B");
+    }
+
+    #[test]
+    fn skill_gems() {
+        let data = dat(
+            87,
+            &[
+                (0, &3u64.to_le_bytes()),
+                (32, &10u32.to_le_bytes()),
+                (36, &20u32.to_le_bytes()),
+                (40, &30u32.to_le_bytes()),
+                (83, &2u32.to_le_bytes()),
+            ],
+        );
+        let file = DatFile::<SkillGems>::try_new(data).unwrap();
+        let row = file.get(0).unwrap();
+
+        assert_eq!(row.base_item_type.unwrap().index(), 3);
+        assert_eq!((row.str, row.dex, row.int), (10, 20, 30));
+        assert!(matches!(row.color, Color::Green));
+    }
+
+    #[test]
+    fn invalid_color() {
+        let data = dat(87, &[(83, &5u32.to_le_bytes())]);
+        let file = DatFile::<SkillGems>::try_new(data).unwrap();
+        assert!(file.try_get(0).unwrap().is_err());
     }
 }
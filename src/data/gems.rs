@@ -39,19 +39,18 @@ pub struct Vendor {
     pub class_ids: Option<BTreeSet<String>>,
 }
 
-pub fn generate<F: BundleFs>(fs: F) -> anyhow::Result<Gems> {
+pub fn generate<F: BundleFs>(bundle: &Bundle<F>) -> anyhow::Result<Gems> {
     let vendor_gem_rewards = fetch_vendor_gem_rewards()?;
     tracing::info!(
         "fetched vendor rewards for {} gems",
         vendor_gem_rewards.len()
     );
 
-    let bundle = Bundle::new(fs);
     let index = bundle.index()?;
 
     macro_rules! read {
         ($name:ident, $type:ty) => {
-            let Some($name) = index.read::<$type>()? else {
+            let Some($name) = index.read::<$type>()?.transpose()? else {
                 anyhow::bail!("{} table does not exist", stringify!($type));
             };
         };
@@ -61,10 +60,12 @@ pub fn generate<F: BundleFs>(fs: F) -> anyhow::Result<Gems> {
     read!(skill_gems, SkillGems);
 
     let mut gems = Vec::with_capacity(skill_gems.len());
-    for sg in skill_gems.iter() {
-        let bit = bits
-            .get(sg.base_item_type as usize)
-            .with_context(|| format!("missing base item type {} for gem", sg.base_item_type))?;
+    for sg in skill_gems.try_iter() {
+        let sg = sg.context("failed to parse skill gem")?;
+        let bit = sg
+            .base_item_type
+            .context("missing base item type for gem")?
+            .resolve(&bits)?;
 
         if bit.site_visibility == 0 {
             continue;
@@ -1,4 +1,4 @@
-use crate::BundleFs;
+use crate::{Bundle, BundleFs};
 
 mod gems;
 mod wiki;
@@ -10,9 +10,9 @@ pub struct Data {
     pub gems: Gems,
 }
 
-pub fn generate<F: BundleFs>(fs: F) -> anyhow::Result<Data> {
+pub fn generate<F: BundleFs>(bundle: &Bundle<F>) -> anyhow::Result<Data> {
     tracing::info!("generating gem info");
-    let gems = gems::generate(fs)?;
+    let gems = gems::generate(bundle)?;
 
     Ok(Data { gems })
 }
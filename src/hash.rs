@@ -1,9 +1,16 @@
+/// Hash function used for file paths in the bundle index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub enum HashStrategy {
+    /// FNV1a, used since patch 3.11.2.
     Fnv3_11_2,
+    /// MurmurHash64A, used since patch 3.21.2.
+    #[default]
     Murmur3_21_2,
 }
 
 impl HashStrategy {
+    pub const ALL: [Self; 2] = [Self::Murmur3_21_2, Self::Fnv3_11_2];
+
     pub fn path(&self, path: &str) -> u64 {
         match self {
             Self::Fnv3_11_2 => {
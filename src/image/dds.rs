@@ -0,0 +1,758 @@
+//! Decoder for the DDS textures shipped with the game.
+//!
+//! Only the first mip level of 2D textures is decoded, supported are the
+//! block compression formats BC1, BC3 and BC7 as well as uncompressed 32 bit textures.
+
+#[derive(Debug, thiserror::Error)]
+pub enum DdsError {
+    #[error("not a dds file")]
+    InvalidMagic,
+    #[error("dds file is truncated")]
+    Truncated,
+    #[error("unsupported dds format: {0}")]
+    UnsupportedFormat(String),
+}
+
+const MAGIC: &[u8; 4] = b"DDS ";
+const HEADER_SIZE: usize = 124;
+const DX10_HEADER_SIZE: usize = 20;
+
+/// Pixel format contains an alpha channel.
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+/// Pixel format is described by a four character code.
+const DDPF_FOURCC: u32 = 0x4;
+/// Pixel format contains uncompressed RGB data.
+const DDPF_RGB: u32 = 0x40;
+
+#[derive(Debug, Clone, Copy)]
+enum Format {
+    Bc1,
+    Bc3,
+    Bc7,
+    /// Uncompressed pixels of 32 bits with the channel masks in RGBA order.
+    Masked([u32; 4]),
+}
+
+/// Decodes a DDS texture into RGBA pixels.
+///
+/// Returns the width, height and the pixel data.
+pub(crate) fn decode(data: &[u8]) -> Result<(u32, u32, Vec<u8>), DdsError> {
+    if !data.starts_with(MAGIC) {
+        return Err(DdsError::InvalidMagic);
+    }
+
+    let header = data
+        .get(MAGIC.len()..MAGIC.len() + HEADER_SIZE)
+        .ok_or(DdsError::Truncated)?;
+    let u32_at = |data: &[u8], offset: usize| {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    };
+
+    let height = u32_at(header, 8);
+    let width = u32_at(header, 12);
+
+    let pf_flags = u32_at(header, 76);
+    let four_cc = &header[80..84];
+    let bit_count = u32_at(header, 84);
+    let masks = [
+        u32_at(header, 88),
+        u32_at(header, 92),
+        u32_at(header, 96),
+        match pf_flags & DDPF_ALPHAPIXELS {
+            0 => 0,
+            _ => u32_at(header, 100),
+        },
+    ];
+
+    let mut offset = MAGIC.len() + HEADER_SIZE;
+
+    let format = if pf_flags & DDPF_FOURCC != 0 {
+        match four_cc {
+            b"DXT1" => Format::Bc1,
+            b"DXT5" => Format::Bc3,
+            b"DX10" => {
+                let dx10 = data
+                    .get(offset..offset + DX10_HEADER_SIZE)
+                    .ok_or(DdsError::Truncated)?;
+                offset += DX10_HEADER_SIZE;
+
+                match u32_at(dx10, 0) {
+                    // R8G8B8A8_TYPELESS, R8G8B8A8_UNORM, R8G8B8A8_UNORM_SRGB
+                    27..=29 => Format::Masked([0xff, 0xff00, 0xff0000, 0xff000000]),
+                    // BC1_TYPELESS, BC1_UNORM, BC1_UNORM_SRGB
+                    70..=72 => Format::Bc1,
+                    // BC3_TYPELESS, BC3_UNORM, BC3_UNORM_SRGB
+                    76..=78 => Format::Bc3,
+                    // B8G8R8A8_UNORM, B8G8R8A8_TYPELESS, B8G8R8A8_UNORM_SRGB
+                    87 | 90 | 91 => Format::Masked([0xff0000, 0xff00, 0xff, 0xff000000]),
+                    // BC7_TYPELESS, BC7_UNORM, BC7_UNORM_SRGB
+                    97..=99 => Format::Bc7,
+                    format => {
+                        return Err(DdsError::UnsupportedFormat(format!("dxgi format {format}")))
+                    }
+                }
+            }
+            four_cc => {
+                return Err(DdsError::UnsupportedFormat(
+                    String::from_utf8_lossy(four_cc).into_owned(),
+                ))
+            }
+        }
+    } else if pf_flags & DDPF_RGB != 0 && bit_count == 32 {
+        Format::Masked(masks)
+    } else {
+        return Err(DdsError::UnsupportedFormat(format!(
+            "pixel format flags {pf_flags:#x} with {bit_count} bits"
+        )));
+    };
+
+    let data = &data[offset..];
+    let pixels = match format {
+        Format::Bc1 => decode_blocks(data, width, height, 8, bc1)?,
+        Format::Bc3 => decode_blocks(data, width, height, 16, bc3)?,
+        Format::Bc7 => decode_blocks(data, width, height, 16, bc7)?,
+        Format::Masked(masks) => decode_masked(data, width, height, masks)?,
+    };
+
+    Ok((width, height, pixels))
+}
+
+/// Decodes a texture made of 4x4 pixel blocks of `block_size` bytes each.
+fn decode_blocks(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    block_size: usize,
+    decode_block: fn(&[u8], &mut [[u8; 4]; 16]),
+) -> Result<Vec<u8>, DdsError> {
+    let (width, height) = (width as usize, height as usize);
+    let blocks_x = width.div_ceil(4);
+    let blocks_y = height.div_ceil(4);
+
+    let len = blocks_x
+        .checked_mul(blocks_y)
+        .and_then(|blocks| blocks.checked_mul(block_size))
+        .ok_or(DdsError::Truncated)?;
+    let data = data.get(..len).ok_or(DdsError::Truncated)?;
+
+    let mut pixels = vec![0; width * height * 4];
+    let mut block = [[0; 4]; 16];
+
+    for (i, compressed) in data.chunks_exact(block_size).enumerate() {
+        decode_block(compressed, &mut block);
+
+        let bx = (i % blocks_x) * 4;
+        let by = (i / blocks_x) * 4;
+        for y in 0..4.min(height - by) {
+            for x in 0..4.min(width - bx) {
+                let offset = ((by + y) * width + bx + x) * 4;
+                pixels[offset..offset + 4].copy_from_slice(&block[y * 4 + x]);
+            }
+        }
+    }
+
+    Ok(pixels)
+}
+
+/// Decodes uncompressed 32 bit pixels with arbitrary channel masks.
+fn decode_masked(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    masks: [u32; 4],
+) -> Result<Vec<u8>, DdsError> {
+    let len = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(4))
+        .ok_or(DdsError::Truncated)?;
+    let data = data.get(..len).ok_or(DdsError::Truncated)?;
+
+    let channel = |pixel: u32, mask: u32| match mask {
+        0 => 0xff,
+        mask => {
+            let value = (pixel & mask) >> mask.trailing_zeros();
+            let max = mask >> mask.trailing_zeros();
+            ((value * 255 + max / 2) / max) as u8
+        }
+    };
+
+    Ok(data
+        .chunks_exact(4)
+        .flat_map(|pixel| {
+            let pixel = u32::from_le_bytes(pixel.try_into().unwrap());
+            masks.map(|mask| channel(pixel, mask))
+        })
+        .collect())
+}
+
+/// Expands a 5:6:5 color into 8 bit channels.
+fn rgb565(color: u16) -> [u8; 3] {
+    let r = ((color >> 11) & 0x1f) as u8;
+    let g = ((color >> 5) & 0x3f) as u8;
+    let b = (color & 0x1f) as u8;
+    [
+        (r << 3) | (r >> 2),
+        (g << 2) | (g >> 4),
+        (b << 3) | (b >> 2),
+    ]
+}
+
+/// Decodes the color part of a BC1, BC2 or BC3 block.
+fn color_block(data: &[u8], opaque: bool, out: &mut [[u8; 4]; 16]) {
+    let c0 = u16::from_le_bytes([data[0], data[1]]);
+    let c1 = u16::from_le_bytes([data[2], data[3]]);
+    let (rgb0, rgb1) = (rgb565(c0), rgb565(c1));
+
+    // Interpolated colors are truncated like the reference decoders and ImageMagick do.
+    let mix = |w0: u16, w1: u16| {
+        let mix = |a: u8, b: u8| ((w0 * a as u16 + w1 * b as u16) / (w0 + w1)) as u8;
+        [
+            mix(rgb0[0], rgb1[0]),
+            mix(rgb0[1], rgb1[1]),
+            mix(rgb0[2], rgb1[2]),
+            255,
+        ]
+    };
+
+    let colors = if c0 > c1 || opaque {
+        [mix(1, 0), mix(0, 1), mix(2, 1), mix(1, 2)]
+    } else {
+        [mix(1, 0), mix(0, 1), mix(1, 1), [0; 4]]
+    };
+
+    let indices = u32::from_le_bytes(data[4..8].try_into().unwrap());
+    for (i, pixel) in out.iter_mut().enumerate() {
+        *pixel = colors[(indices >> (i * 2)) as usize & 0x3];
+    }
+}
+
+fn bc1(data: &[u8], out: &mut [[u8; 4]; 16]) {
+    color_block(data, false, out);
+}
+
+fn bc3(data: &[u8], out: &mut [[u8; 4]; 16]) {
+    color_block(&data[8..], true, out);
+
+    let (a0, a1) = (data[0] as u16, data[1] as u16);
+    let mut alpha = [0; 8];
+    alpha[0] = a0 as u8;
+    alpha[1] = a1 as u8;
+    if a0 > a1 {
+        for i in 1..7 {
+            alpha[i + 1] = (((7 - i as u16) * a0 + i as u16 * a1) / 7) as u8;
+        }
+    } else {
+        for i in 1..5 {
+            alpha[i + 1] = (((5 - i as u16) * a0 + i as u16 * a1) / 5) as u8;
+        }
+        alpha[6] = 0;
+        alpha[7] = 255;
+    }
+
+    let mut indices = [0; 8];
+    indices[..6].copy_from_slice(&data[2..8]);
+    let indices = u64::from_le_bytes(indices);
+    for (i, pixel) in out.iter_mut().enumerate() {
+        pixel[3] = alpha[(indices >> (i * 3)) as usize & 0x7];
+    }
+}
+
+/// Reads bits from a BC7 block starting with the least significant bit.
+struct Bits(u128);
+
+impl Bits {
+    fn read(&mut self, count: u32) -> u8 {
+        let value = self.0 & ((1 << count) - 1);
+        self.0 >>= count;
+        value as u8
+    }
+}
+
+/// Properties of the BC7 block modes.
+struct Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    /// Whether every endpoint has a unique p-bit.
+    endpoint_pbits: bool,
+    /// Whether every subset has a shared p-bit.
+    shared_pbits: bool,
+    index_bits: u32,
+    /// Bits of the separate alpha indices, zero if colors and alpha share indices.
+    index2_bits: u32,
+}
+
+#[rustfmt::skip]
+const MODES: [Mode; 8] = [
+    Mode { subsets: 3, partition_bits: 4, rotation_bits: 0, index_selection_bits: 0, color_bits: 4, alpha_bits: 0, endpoint_pbits: true, shared_pbits: false, index_bits: 3, index2_bits: 0 },
+    Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 6, alpha_bits: 0, endpoint_pbits: false, shared_pbits: true, index_bits: 3, index2_bits: 0 },
+    Mode { subsets: 3, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 5, alpha_bits: 0, endpoint_pbits: false, shared_pbits: false, index_bits: 2, index2_bits: 0 },
+    Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 7, alpha_bits: 0, endpoint_pbits: true, shared_pbits: false, index_bits: 2, index2_bits: 0 },
+    Mode { subsets: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 1, color_bits: 5, alpha_bits: 6, endpoint_pbits: false, shared_pbits: false, index_bits: 2, index2_bits: 3 },
+    Mode { subsets: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 0, color_bits: 7, alpha_bits: 8, endpoint_pbits: false, shared_pbits: false, index_bits: 2, index2_bits: 2 },
+    Mode { subsets: 1, partition_bits: 0, rotation_bits: 0, index_selection_bits: 0, color_bits: 7, alpha_bits: 7, endpoint_pbits: true, shared_pbits: false, index_bits: 4, index2_bits: 0 },
+    Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 5, alpha_bits: 5, endpoint_pbits: true, shared_pbits: false, index_bits: 2, index2_bits: 0 },
+];
+
+const WEIGHTS_2: [u16; 4] = [0, 21, 43, 64];
+const WEIGHTS_3: [u16; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const WEIGHTS_4: [u16; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+fn bc7(data: &[u8], out: &mut [[u8; 4]; 16]) {
+    let mut bits = Bits(u128::from_le_bytes(data.try_into().unwrap()));
+
+    let mode = bits.0.trailing_zeros() as usize;
+    let Some(m) = MODES.get(mode) else {
+        // Reserved mode, decoded as transparent black.
+        *out = [[0; 4]; 16];
+        return;
+    };
+    bits.read(mode as u32 + 1);
+
+    let partition = bits.read(m.partition_bits) as usize;
+    let rotation = bits.read(m.rotation_bits);
+    let index_selection = bits.read(m.index_selection_bits) != 0;
+
+    let mut endpoints = [[0u8; 4]; 6];
+    let endpoints = &mut endpoints[..m.subsets * 2];
+    for channel in 0..3 {
+        for endpoint in endpoints.iter_mut() {
+            endpoint[channel] = bits.read(m.color_bits);
+        }
+    }
+    for endpoint in endpoints.iter_mut() {
+        endpoint[3] = bits.read(m.alpha_bits);
+    }
+
+    let (mut color_bits, mut alpha_bits) = (m.color_bits, m.alpha_bits);
+    if m.endpoint_pbits || m.shared_pbits {
+        let pbits = match m.shared_pbits {
+            true => m.subsets,
+            false => endpoints.len(),
+        };
+        let pbits = (0..pbits).map(|_| bits.read(1)).collect::<Vec<_>>();
+
+        for (i, endpoint) in endpoints.iter_mut().enumerate() {
+            let pbit = match m.shared_pbits {
+                true => pbits[i / 2],
+                false => pbits[i],
+            };
+            for channel in endpoint.iter_mut() {
+                *channel = (*channel << 1) | pbit;
+            }
+        }
+
+        color_bits += 1;
+        if alpha_bits > 0 {
+            alpha_bits += 1;
+        }
+    }
+
+    for endpoint in endpoints.iter_mut() {
+        for channel in &mut endpoint[..3] {
+            *channel = expand(*channel, color_bits);
+        }
+        endpoint[3] = match alpha_bits {
+            0 => 255,
+            bits => expand(endpoint[3], bits),
+        };
+    }
+
+    let subset = |i: usize| match m.subsets {
+        2 => (PARTITIONS_2[partition] >> (i * 2)) as usize & 0x3,
+        3 => (PARTITIONS_3[partition] >> (i * 2)) as usize & 0x3,
+        _ => 0,
+    };
+    let is_anchor = |i: usize| match m.subsets {
+        2 => i == 0 || i == ANCHORS_2[partition] as usize,
+        3 => i == 0 || ANCHORS_3[partition].contains(&(i as u8)),
+        _ => i == 0,
+    };
+
+    // Anchor indices are stored with one bit less, their most significant bit is always zero.
+    let mut read_indices = |count: u32| {
+        let mut indices = [0; 16];
+        for (i, index) in indices.iter_mut().enumerate() {
+            *index = bits.read(count - is_anchor(i) as u32);
+        }
+        indices
+    };
+
+    let indices = read_indices(m.index_bits);
+    let indices2 = match m.index2_bits {
+        0 => None,
+        count => Some(read_indices(count)),
+    };
+
+    let weights = |count: u32| match count {
+        2 => &WEIGHTS_2[..],
+        3 => &WEIGHTS_3[..],
+        _ => &WEIGHTS_4[..],
+    };
+
+    for (i, pixel) in out.iter_mut().enumerate() {
+        let s = subset(i);
+        let (e0, e1) = (endpoints[s * 2], endpoints[s * 2 + 1]);
+
+        let (color, alpha) = match indices2 {
+            None => {
+                let w = weights(m.index_bits)[indices[i] as usize];
+                (w, w)
+            }
+            Some(ref indices2) => {
+                let w1 = weights(m.index_bits)[indices[i] as usize];
+                let w2 = weights(m.index2_bits)[indices2[i] as usize];
+                match index_selection {
+                    false => (w1, w2),
+                    true => (w2, w1),
+                }
+            }
+        };
+
+        *pixel = [
+            interpolate(e0[0], e1[0], color),
+            interpolate(e0[1], e1[1], color),
+            interpolate(e0[2], e1[2], color),
+            interpolate(e0[3], e1[3], alpha),
+        ];
+
+        match rotation {
+            1 => pixel.swap(0, 3),
+            2 => pixel.swap(1, 3),
+            3 => pixel.swap(2, 3),
+            _ => {}
+        }
+    }
+}
+
+/// Expands a value of `bits` bits to 8 bits by replicating its most significant bits.
+fn expand(value: u8, bits: u32) -> u8 {
+    if bits >= 8 {
+        return value;
+    }
+    let value = value << (8 - bits);
+    value | (value >> bits)
+}
+
+fn interpolate(e0: u8, e1: u8, weight: u16) -> u8 {
+    (((64 - weight) * e0 as u16 + weight * e1 as u16 + 32) >> 6) as u8
+}
+
+/// Subset of every pixel for the two subset partitions, two bits per pixel.
+const PARTITIONS_2: [u32; 64] = [
+    0x50505050, 0x40404040, 0x54545454, 0x54505040, 0x50404000, 0x55545450, 0x55545040, 0x54504000,
+    0x50400000, 0x55555450, 0x55544000, 0x54400000, 0x55555440, 0x55550000, 0x55555500, 0x55000000,
+    0x55150100, 0x00004054, 0x15010000, 0x00405054, 0x00004050, 0x15050100, 0x05010000, 0x40505054,
+    0x00404050, 0x05010100, 0x14141414, 0x05141450, 0x01155440, 0x00555500, 0x15014054, 0x05414150,
+    0x44444444, 0x55005500, 0x11441144, 0x05055050, 0x05500550, 0x11114444, 0x41144114, 0x44111144,
+    0x15055054, 0x01055040, 0x05041050, 0x05455150, 0x14414114, 0x50050550, 0x41411414, 0x00141400,
+    0x00041504, 0x00105410, 0x10541000, 0x04150400, 0x50410514, 0x41051450, 0x05415014, 0x14054150,
+    0x41050514, 0x41505014, 0x40011554, 0x54150140, 0x50505500, 0x00555050, 0x15151010, 0x54540404,
+];
+
+/// Subset of every pixel for the three subset partitions, two bits per pixel.
+const PARTITIONS_3: [u32; 64] = [
+    0xaa685050, 0x6a5a5040, 0x5a5a4200, 0x5450a0a8, 0xa5a50000, 0xa0a05050, 0x5555a0a0, 0x5a5a5050,
+    0xaa550000, 0xaa555500, 0xaaaa5500, 0x90909090, 0x94949494, 0xa4a4a4a4, 0xa9a59450, 0x2a0a4250,
+    0xa5945040, 0x0a425054, 0xa5a5a500, 0x55a0a0a0, 0xa8a85454, 0x6a6a4040, 0xa4a45000, 0x1a1a0500,
+    0x0050a4a4, 0xaaa59090, 0x14696914, 0x69691400, 0xa08585a0, 0xaa821414, 0x50a4a450, 0x6a5a0200,
+    0xa9a58000, 0x5090a0a8, 0xa8a09050, 0x24242424, 0x00aa5500, 0x24924924, 0x24499224, 0x50a50a50,
+    0x500aa550, 0xaaaa4444, 0x66660000, 0xa5a0a5a0, 0x50a050a0, 0x69286928, 0x44aaaa44, 0x66666600,
+    0xaa444444, 0x54a854a8, 0x95809580, 0x96969600, 0xa85454a8, 0x80959580, 0xaa141414, 0x96960000,
+    0xaaaa1414, 0xa05050a0, 0xa0a5a5a0, 0x96000000, 0x40804080, 0xa9a8a9a8, 0xaaaaaa44, 0x2a4a5254,
+];
+
+/// Anchor index of the second subset for the two subset partitions.
+const ANCHORS_2: [u8; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 2, 8, 2, 2, 8, 8, 15, 2, 8,
+    2, 2, 8, 8, 2, 2, 15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6, 6, 2, 6, 8, 15, 15, 2,
+    2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+/// Anchor indices of the second and third subset for the three subset partitions.
+const ANCHORS_3: [[u8; 2]; 64] = [
+    [3, 15],
+    [3, 8],
+    [8, 15],
+    [3, 15],
+    [8, 15],
+    [3, 15],
+    [3, 15],
+    [8, 15],
+    [8, 15],
+    [8, 15],
+    [6, 15],
+    [6, 15],
+    [6, 15],
+    [5, 15],
+    [3, 15],
+    [3, 8],
+    [3, 15],
+    [3, 8],
+    [8, 15],
+    [3, 15],
+    [3, 15],
+    [3, 8],
+    [6, 15],
+    [8, 10],
+    [3, 5],
+    [8, 15],
+    [6, 8],
+    [6, 10],
+    [8, 15],
+    [5, 15],
+    [10, 15],
+    [8, 15],
+    [8, 15],
+    [3, 15],
+    [3, 15],
+    [5, 10],
+    [6, 10],
+    [8, 10],
+    [8, 9],
+    [10, 15],
+    [6, 15],
+    [3, 15],
+    [8, 15],
+    [5, 15],
+    [3, 15],
+    [6, 15],
+    [6, 15],
+    [8, 15],
+    [3, 15],
+    [3, 15],
+    [5, 15],
+    [5, 15],
+    [5, 15],
+    [8, 15],
+    [5, 15],
+    [10, 15],
+    [5, 15],
+    [10, 15],
+    [8, 15],
+    [13, 15],
+    [3, 15],
+    [12, 15],
+    [3, 15],
+    [3, 8],
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a DDS file with a four character code or uncompressed pixel format.
+    fn dds(width: u32, height: u32, four_cc: &[u8; 4], dxgi: Option<u32>, data: &[u8]) -> Vec<u8> {
+        let mut header = [0u8; HEADER_SIZE];
+        header[0..4].copy_from_slice(&(HEADER_SIZE as u32).to_le_bytes());
+        header[8..12].copy_from_slice(&height.to_le_bytes());
+        header[12..16].copy_from_slice(&width.to_le_bytes());
+        header[72..76].copy_from_slice(&32u32.to_le_bytes());
+
+        if four_cc == b"\0\0\0\0" {
+            let flags = DDPF_RGB | DDPF_ALPHAPIXELS;
+            header[76..80].copy_from_slice(&flags.to_le_bytes());
+            header[84..88].copy_from_slice(&32u32.to_le_bytes());
+            for (i, mask) in [0xff0000u32, 0xff00, 0xff, 0xff000000].iter().enumerate() {
+                header[88 + i * 4..92 + i * 4].copy_from_slice(&mask.to_le_bytes());
+            }
+        } else {
+            header[76..80].copy_from_slice(&DDPF_FOURCC.to_le_bytes());
+            header[80..84].copy_from_slice(four_cc);
+        }
+
+        let mut file = MAGIC.to_vec();
+        file.extend(header);
+        if let Some(dxgi) = dxgi {
+            let mut dx10 = [0u8; DX10_HEADER_SIZE];
+            dx10[0..4].copy_from_slice(&dxgi.to_le_bytes());
+            file.extend(dx10);
+        }
+        file.extend(data);
+        file
+    }
+
+    fn block(decode: fn(&[u8], &mut [[u8; 4]; 16]), data: &[u8]) -> [[u8; 4]; 16] {
+        let mut out = [[0; 4]; 16];
+        decode(data, &mut out);
+        out
+    }
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+    /// Red and blue endpoints as `c0` and `c1` with pixel `i` using index `i % 4`.
+    const RED_BLUE: [u8; 8] = [0x00, 0xf8, 0x1f, 0x00, 0xe4, 0xe4, 0xe4, 0xe4];
+    /// Blue and red endpoints as `c0` and `c1` with pixel `i` using index `i % 4`.
+    const BLUE_RED: [u8; 8] = [0x1f, 0x00, 0x00, 0xf8, 0xe4, 0xe4, 0xe4, 0xe4];
+
+    #[test]
+    fn bc1_four_colors() {
+        let out = block(bc1, &RED_BLUE);
+        let colors = [RED, BLUE, [170, 0, 85, 255], [85, 0, 170, 255]];
+        for (i, pixel) in out.iter().enumerate() {
+            assert_eq!(*pixel, colors[i % 4], "pixel {i}");
+        }
+    }
+
+    #[test]
+    fn bc1_three_colors_and_alpha() {
+        let out = block(bc1, &BLUE_RED);
+        let colors = [BLUE, RED, [127, 0, 127, 255], [0, 0, 0, 0]];
+        for (i, pixel) in out.iter().enumerate() {
+            assert_eq!(*pixel, colors[i % 4], "pixel {i}");
+        }
+
+        // Equal endpoints also use the three color mode.
+        let out = block(bc1, &[0x00, 0xf8, 0x00, 0xf8, 0xff, 0, 0, 0]);
+        assert_eq!(out[0], [0, 0, 0, 0]);
+        assert_eq!(out[4], RED);
+    }
+
+    #[test]
+    fn bc3_interpolated_alpha() {
+        // Alpha 255 and 0, pixel `i` uses alpha index `i % 8`.
+        let mut data = [255, 0, 0x88, 0xc6, 0xfa, 0x88, 0xc6, 0xfa].to_vec();
+        data.extend(BLUE_RED);
+
+        let out = block(bc3, &data);
+        let alpha = [255, 0, 218, 182, 145, 109, 72, 36];
+        // The color block of BC3 is always opaque, even if `c0 <= c1`.
+        let colors = [BLUE, RED, [85, 0, 170, 255], [170, 0, 85, 255]];
+        for (i, pixel) in out.iter().enumerate() {
+            let [r, g, b, _] = colors[i % 4];
+            assert_eq!(*pixel, [r, g, b, alpha[i % 8]], "pixel {i}");
+        }
+    }
+
+    #[test]
+    fn bc3_explicit_alpha() {
+        // Alpha 0 and 255, pixel `i` uses alpha index `i % 8`.
+        let mut data = [0, 255, 0x88, 0xc6, 0xfa, 0x88, 0xc6, 0xfa].to_vec();
+        data.extend(RED_BLUE);
+
+        let out = block(bc3, &data);
+        let alpha = [0, 255, 51, 102, 153, 204, 0, 255];
+        for (i, pixel) in out.iter().enumerate() {
+            assert_eq!(pixel[3], alpha[i % 8], "pixel {i}");
+        }
+    }
+
+    /// Blocks and their expected pixels, decoded with bcdec.
+    #[rustfmt::skip]
+    const BC7: [([u8; 16], [u8; 64]); 8] = [
+        // Mode 0, partition 13.
+        ([187, 181, 105, 126, 193, 245, 110, 131, 219, 14, 204, 77, 32, 215, 29, 158],
+         [207, 156, 158, 255, 87, 174, 212, 255, 197, 121, 116, 255, 78, 116, 102, 255, 207, 156, 158, 255, 214, 231, 198, 255, 168, 120, 113, 255, 168, 120, 113, 255, 193, 105, 111, 255, 108, 184, 210, 255, 136, 118, 109, 255, 255, 123, 123, 255, 214, 181, 181, 255, 87, 174, 212, 255, 136, 118, 109, 255, 107, 117, 106, 255]),
+        // Mode 1, partition 34.
+        ([138, 54, 97, 144, 229, 145, 206, 7, 123, 116, 204, 141, 54, 12, 5, 95],
+         [132, 98, 91, 255, 94, 188, 79, 255, 132, 98, 91, 255, 75, 182, 65, 255, 94, 188, 79, 255, 44, 45, 156, 255, 58, 176, 53, 255, 189, 132, 49, 255, 101, 79, 114, 255, 41, 171, 40, 255, 101, 79, 114, 255, 58, 176, 53, 255, 24, 165, 28, 255, 44, 45, 156, 255, 145, 205, 116, 255, 160, 115, 70, 255]),
+        // Mode 2, partition 5.
+        ([44, 216, 149, 138, 67, 193, 45, 214, 89, 242, 246, 247, 156, 156, 59, 32],
+         [129, 87, 175, 255, 129, 87, 175, 255, 104, 192, 206, 255, 148, 181, 107, 255, 159, 160, 204, 255, 189, 231, 231, 255, 148, 181, 107, 255, 82, 198, 255, 255, 129, 87, 175, 255, 189, 231, 231, 255, 199, 211, 200, 255, 231, 214, 222, 255, 99, 16, 148, 255, 99, 16, 148, 255, 199, 211, 200, 255, 231, 214, 222, 255]),
+        // Mode 3, partition 63.
+        ([248, 63, 203, 163, 91, 19, 133, 75, 107, 170, 195, 209, 107, 184, 228, 169],
+         [159, 155, 53, 255, 84, 145, 114, 255, 203, 81, 171, 255, 159, 155, 53, 255, 159, 155, 53, 255, 111, 211, 71, 255, 173, 131, 92, 255, 173, 131, 92, 255, 189, 105, 132, 255, 71, 113, 135, 255, 111, 211, 71, 255, 111, 211, 71, 255, 159, 155, 53, 255, 84, 145, 114, 255, 84, 145, 114, 255, 84, 145, 114, 255]),
+        // Mode 4, rotation 2 and swapped index selection.
+        ([208, 139, 226, 214, 176, 133, 78, 170, 221, 142, 141, 66, 73, 211, 169, 142],
+         [111, 113, 133, 172, 101, 113, 120, 185, 111, 138, 133, 172, 101, 89, 120, 185, 133, 113, 160, 145, 111, 113, 133, 172, 111, 113, 133, 172, 111, 162, 133, 172, 122, 138, 145, 160, 111, 162, 133, 172, 165, 138, 198, 107, 133, 113, 160, 145, 111, 162, 133, 172, 144, 113, 172, 133, 122, 89, 145, 160, 133, 162, 160, 145]),
+        // Mode 5, rotation 1.
+        ([96, 21, 171, 232, 184, 255, 116, 244, 8, 3, 31, 215, 86, 71, 133, 173],
+         [40, 68, 247, 42, 40, 93, 186, 85, 40, 68, 247, 42, 40, 118, 123, 130, 61, 93, 186, 85, 40, 68, 247, 42, 29, 68, 247, 42, 40, 118, 123, 130, 40, 143, 62, 173, 40, 143, 62, 173, 29, 68, 247, 42, 51, 118, 123, 130, 40, 143, 62, 173, 61, 118, 123, 130, 51, 118, 123, 130, 51, 93, 186, 85]),
+        // Mode 6.
+        ([192, 254, 136, 211, 86, 252, 60, 222, 42, 79, 192, 29, 235, 77, 226, 15],
+         [192, 110, 55, 103, 226, 80, 36, 79, 70, 218, 126, 188, 203, 100, 49, 95, 251, 57, 21, 61, 107, 185, 105, 162, 95, 195, 111, 170, 240, 67, 28, 69, 118, 175, 98, 154, 81, 208, 119, 180, 95, 195, 111, 170, 203, 100, 49, 95, 226, 80, 36, 79, 81, 208, 119, 180, 70, 218, 126, 188, 251, 57, 21, 61]),
+        // Mode 7, partition 20.
+        ([128, 212, 7, 132, 214, 15, 122, 102, 72, 238, 255, 80, 23, 127, 30, 148],
+         [171, 199, 171, 232, 84, 226, 133, 242, 36, 4, 69, 125, 36, 4, 69, 125, 0, 251, 97, 251, 0, 251, 97, 251, 0, 251, 97, 251, 79, 81, 96, 107, 84, 226, 133, 242, 0, 251, 97, 251, 171, 199, 171, 232, 255, 174, 207, 223, 255, 174, 207, 223, 171, 199, 171, 232, 171, 199, 171, 232, 84, 226, 133, 242]),
+    ];
+
+    #[test]
+    fn bc7_modes() {
+        for (mode, (data, expected)) in BC7.iter().enumerate() {
+            let out = block(bc7, data);
+            assert_eq!(out.concat(), expected, "mode {mode}");
+        }
+    }
+
+    #[test]
+    fn bc7_reserved_mode() {
+        assert_eq!(block(bc7, &[0; 16]), [[0; 4]; 16]);
+    }
+
+    #[test]
+    fn decode_partial_blocks() {
+        // 5x3 pixels are covered by 2x1 blocks, the second block is only partially used.
+        let mut data = RED_BLUE.to_vec();
+        data.extend([0x00, 0xf8, 0x1f, 0x00, 0x55, 0x55, 0x55, 0x55]);
+
+        let (width, height, pixels) = decode(&dds(5, 3, b"DXT1", None, &data)).unwrap();
+        assert_eq!((width, height), (5, 3));
+        assert_eq!(pixels.len(), 5 * 3 * 4);
+
+        let pixel = |x: usize, y: usize| &pixels[(y * 5 + x) * 4..][..4];
+        for y in 0..3 {
+            assert_eq!(pixel(0, y), RED);
+            assert_eq!(pixel(1, y), BLUE);
+            assert_eq!(pixel(4, y), BLUE);
+        }
+    }
+
+    #[test]
+    fn decode_formats() {
+        let (_, _, pixels) = decode(&dds(4, 4, b"DX10", Some(98), &BC7[6].0)).unwrap();
+        assert_eq!(pixels, BC7[6].1);
+
+        let mut bc3 = [255, 255, 0, 0, 0, 0, 0, 0].to_vec();
+        bc3.extend(RED_BLUE);
+        let (_, _, pixels) = decode(&dds(4, 4, b"DXT5", None, &bc3)).unwrap();
+        assert_eq!(pixels[..4], RED);
+
+        // Uncompressed BGRA.
+        let (_, _, pixels) = decode(&dds(1, 1, b"\0\0\0\0", None, &[1, 2, 3, 4])).unwrap();
+        assert_eq!(pixels, [3, 2, 1, 4]);
+        let (_, _, pixels) = decode(&dds(1, 1, b"DX10", Some(28), &[1, 2, 3, 4])).unwrap();
+        assert_eq!(pixels, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn unsupported() {
+        let result = decode(&dds(4, 4, b"ATI2", None, &[0; 16]));
+        assert!(matches!(result, Err(DdsError::UnsupportedFormat(_))));
+        let result = decode(&dds(4, 4, b"DX10", Some(2), &[0; 16]));
+        assert!(matches!(result, Err(DdsError::UnsupportedFormat(_))));
+        assert!(matches!(decode(b"PNG "), Err(DdsError::InvalidMagic)));
+    }
+
+    #[test]
+    fn truncated() {
+        let files = [
+            dds(5, 3, b"DXT1", None, &[0; 16]),
+            dds(4, 4, b"DXT5", None, &[0; 16]),
+            dds(4, 4, b"DX10", Some(98), &[0; 16]),
+            dds(2, 2, b"\0\0\0\0", None, &[0; 16]),
+        ];
+
+        for file in files {
+            assert!(decode(&file).is_ok());
+            for len in 0..file.len() {
+                let result = decode(&file[..len]);
+                assert!(
+                    matches!(result, Err(DdsError::Truncated | DdsError::InvalidMagic)),
+                    "length {len}: {result:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn oversized_dimensions() {
+        for four_cc in [b"DXT1", b"\0\0\0\0"] {
+            let result = decode(&dds(u32::MAX, u32::MAX, four_cc, None, &[0; 64]));
+            assert!(matches!(result, Err(DdsError::Truncated)));
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use std::sync::Once;
+
+pub use magick_rust::MagickError as ImageError;
+use magick_rust::{
+    bindings::{
+        CompositeOperator_CopyCompositeOp, CompositeOperator_DstInCompositeOp,
+        CompositeOperator_DstOverCompositeOp, CompositeOperator_MultiplyCompositeOp,
+    },
+    magick_wand_genesis, MagickError, MagickWand, PixelWand,
+};
+
+use super::{GemLayer, OutputFormat};
+
+static MAGICK: Once = Once::new();
+
+fn ensure_init() {
+    MAGICK.call_once(magick_wand_genesis);
+}
+
+#[derive(Clone)]
+pub struct Dds {
+    wand: MagickWand,
+}
+
+impl Dds {
+    /// Creates a new transparent image.
+    pub fn new(width: u32, height: u32) -> Result<Self, MagickError> {
+        ensure_init();
+
+        let wand = MagickWand::new();
+        let mut background = PixelWand::new();
+        background.set_color("none")?;
+        wand.new_image(width as usize, height as usize, &background)?;
+
+        Ok(Self { wand })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.wand.get_image_width() as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        self.wand.get_image_height() as u32
+    }
+
+    pub fn flask(&mut self) -> Result<(), MagickError> {
+        let width = self.wand.get_image_width() / 3;
+        let height = self.wand.get_image_height();
+
+        let layer1 = self.wand.clone();
+        let layer2 = self.wand.clone();
+
+        layer1.crop_image(width, height, width as isize, 0)?;
+        layer2.crop_image(width, height, width as isize * 2, 0)?;
+        self.wand.crop_image(width, height, 0, 0)?;
+
+        // No clue if this is correct, it looks alright...
+        layer2.compose_images(&layer1, CompositeOperator_DstOverCompositeOp, true, 0, 0)?;
+        self.wand
+            .compose_images(&layer2, CompositeOperator_DstOverCompositeOp, true, 0, 0)?;
+
+        Ok(())
+    }
+
+    /// Renders a gem icon from a layered gem texture.
+    ///
+    /// Layered gem textures contain three layers side by side, the gem, the shading
+    /// of the transfigured variants and the background. Textures of older gems only
+    /// contain the gem and are left as is.
+    pub fn gem(&mut self) -> Result<(), MagickError> {
+        self.gem_layers(false)
+    }
+
+    /// Renders the icon of a transfigured gem from a layered gem texture, the gem is
+    /// multiplied with the shading layer, see [`Self::gem`].
+    pub fn transfigured_gem(&mut self) -> Result<(), MagickError> {
+        self.gem_layers(true)
+    }
+
+    fn gem_layers(&mut self, transfigured: bool) -> Result<(), MagickError> {
+        let width = self.wand.get_image_width();
+        let height = self.wand.get_image_height();
+
+        let Some(width) = super::gem_layer_width(width as u32, height as u32) else {
+            return Ok(());
+        };
+        let width = width as usize;
+
+        let layer = |layer: GemLayer| {
+            let wand = self.wand.clone();
+            wand.crop_image(width, height, (width * layer as usize) as isize, 0)
+                .map(|_| wand)
+        };
+
+        let background = layer(GemLayer::Background)?;
+        let shading = match transfigured {
+            true => Some(layer(GemLayer::Shading)?),
+            false => None,
+        };
+
+        self.wand
+            .crop_image(width, height, (width * GemLayer::Gem as usize) as isize, 0)?;
+        if let Some(shading) = shading {
+            // Multiplying also shades the transparent parts of the gem, restore its alpha.
+            let gem = self.wand.clone();
+            self.wand.compose_images(
+                &shading,
+                CompositeOperator_MultiplyCompositeOp,
+                true,
+                0,
+                0,
+            )?;
+            self.wand
+                .compose_images(&gem, CompositeOperator_DstInCompositeOp, true, 0, 0)?;
+        }
+        self.wand.compose_images(
+            &background,
+            CompositeOperator_DstOverCompositeOp,
+            true,
+            0,
+            0,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn crop(&mut self, pos: (u32, u32), size: (u32, u32)) -> Result<(), MagickError> {
+        self.wand.crop_image(
+            size.0 as usize,
+            size.1 as usize,
+            pos.0 as isize,
+            pos.1 as isize,
+        )
+    }
+
+    /// Replaces the pixels at `pos` with the pixels of `image`.
+    pub fn paste(&mut self, image: &Self, pos: (u32, u32)) -> Result<(), MagickError> {
+        self.wand.compose_images(
+            &image.wand,
+            CompositeOperator_CopyCompositeOp,
+            true,
+            pos.0 as isize,
+            pos.1 as isize,
+        )
+    }
+
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.wand.resize_image(width, height, 0);
+    }
+
+    pub fn encode(&self, format: OutputFormat) -> Result<Vec<u8>, MagickError> {
+        let mut wand = self.wand.clone();
+        match format {
+            OutputFormat::Png => {}
+            OutputFormat::WebpLossless => wand.set_option("webp:lossless", "true")?,
+            OutputFormat::Webp { quality } | OutputFormat::Avif { quality } => {
+                wand.set_image_compression_quality(quality.min(100).into())?
+            }
+        }
+        wand.write_image_blob(format.extension())
+    }
+}
+
+impl TryFrom<&[u8]> for Dds {
+    type Error = MagickError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        ensure_init();
+
+        let wand = MagickWand::new();
+        wand.read_image_blob(value)?;
+
+        Ok(Self { wand })
+    }
+}
@@ -0,0 +1,85 @@
+// Both backends are compiled if enabled, `magick` takes precedence as the backend of `Dds`.
+#[cfg(feature = "native-dds")]
+#[cfg_attr(feature = "magick", allow(dead_code))]
+mod dds;
+#[cfg(feature = "magick")]
+mod magick;
+#[cfg(feature = "native-dds")]
+#[cfg_attr(feature = "magick", allow(dead_code))]
+mod native;
+
+use std::fmt;
+
+#[cfg(feature = "native-dds")]
+pub use self::dds::DdsError;
+#[cfg(feature = "magick")]
+pub use self::magick::*;
+#[cfg(all(feature = "native-dds", not(feature = "magick")))]
+pub use self::native::*;
+
+/// Layers of a layered gem texture, from left to right.
+#[derive(Debug, Clone, Copy)]
+enum GemLayer {
+    Gem = 0,
+    /// Shading multiplied with the gem of transfigured variants.
+    Shading = 1,
+    Background = 2,
+}
+
+/// Width of a single layer of a gem texture, `None` if the texture is not layered.
+fn gem_layer_width(width: u32, height: u32) -> Option<u32> {
+    // Older gem textures are a single square-ish layer.
+    (width >= height + 10).then_some(width / 3)
+}
+
+/// Image format written by the pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    Png,
+    WebpLossless,
+    /// Lossy WebP with a quality from 0 to 100.
+    Webp {
+        quality: u8,
+    },
+    /// AVIF with a quality from 0 to 100.
+    Avif {
+        quality: u8,
+    },
+}
+
+impl OutputFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::WebpLossless | Self::Webp { .. } => "webp",
+            Self::Avif { .. } => "avif",
+        }
+    }
+
+    /// Whether the image backend can encode the format.
+    pub fn is_supported(&self) -> bool {
+        cfg!(feature = "magick") || matches!(self, Self::Png | Self::WebpLossless)
+    }
+}
+
+impl Default for OutputFormat {
+    /// Lossy WebP, or lossless WebP if the image backend can not encode lossy WebP.
+    fn default() -> Self {
+        match cfg!(feature = "magick") {
+            true => Self::Webp { quality: 75 },
+            false => Self::WebpLossless,
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Png => write!(f, "png"),
+            Self::WebpLossless => write!(f, "webp lossless"),
+            Self::Webp { quality } => write!(f, "webp q{quality}"),
+            Self::Avif { quality } => write!(f, "avif q{quality}"),
+        }
+    }
+}
@@ -0,0 +1,413 @@
+use super::{
+    dds::{self, DdsError},
+    GemLayer, OutputFormat,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ImageError {
+    #[error(transparent)]
+    Dds(#[from] DdsError),
+    #[error("crop {size:?} at {pos:?} is outside of the image")]
+    InvalidCrop { pos: (u32, u32), size: (u32, u32) },
+    #[error("unsupported output format '{0}'")]
+    UnsupportedFormat(String),
+    #[error("failed to encode webp: {0}")]
+    Webp(#[from] image_webp::EncodingError),
+    #[error("failed to encode png: {0}")]
+    Png(#[from] png::EncodingError),
+}
+
+/// A decoded texture as RGBA pixels.
+#[derive(Clone)]
+pub struct Dds {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Dds {
+    /// Creates a new transparent image.
+    pub fn new(width: u32, height: u32) -> Result<Self, ImageError> {
+        Ok(Self {
+            width,
+            height,
+            pixels: vec![0; width as usize * height as usize * 4],
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn flask(&mut self) -> Result<(), ImageError> {
+        let width = self.width / 3;
+        let height = self.height;
+
+        let layer1 = self.cropped((width, 0), (width, height))?;
+        let mut layer2 = self.cropped((width * 2, 0), (width, height))?;
+        self.crop((0, 0), (width, height))?;
+
+        // No clue if this is correct, it looks alright...
+        layer2.compose_over(&layer1);
+        self.compose_over(&layer2);
+
+        Ok(())
+    }
+
+    /// Renders a gem icon from a layered gem texture.
+    ///
+    /// Layered gem textures contain three layers side by side, the gem, the shading
+    /// of the transfigured variants and the background. Textures of older gems only
+    /// contain the gem and are left as is.
+    pub fn gem(&mut self) -> Result<(), ImageError> {
+        self.gem_layers(false)
+    }
+
+    /// Renders the icon of a transfigured gem from a layered gem texture, the gem is
+    /// multiplied with the shading layer, see [`Self::gem`].
+    pub fn transfigured_gem(&mut self) -> Result<(), ImageError> {
+        self.gem_layers(true)
+    }
+
+    fn gem_layers(&mut self, transfigured: bool) -> Result<(), ImageError> {
+        let height = self.height;
+        let Some(width) = super::gem_layer_width(self.width, height) else {
+            return Ok(());
+        };
+        let layer = |layer: GemLayer| self.cropped((width * layer as u32, 0), (width, height));
+
+        let background = layer(GemLayer::Background)?;
+        let shading = match transfigured {
+            true => Some(layer(GemLayer::Shading)?),
+            false => None,
+        };
+
+        self.crop((width * GemLayer::Gem as u32, 0), (width, height))?;
+        if let Some(shading) = shading {
+            self.multiply(&shading);
+        }
+        self.compose_over(&background);
+
+        Ok(())
+    }
+
+    pub fn crop(&mut self, pos: (u32, u32), size: (u32, u32)) -> Result<(), ImageError> {
+        *self = self.cropped(pos, size)?;
+        Ok(())
+    }
+
+    /// Replaces the pixels at `pos` with the pixels of `image`.
+    pub fn paste(&mut self, image: &Self, pos: (u32, u32)) -> Result<(), ImageError> {
+        let x = pos.0.min(self.width) as usize;
+        let width = (image.width as usize).min(self.width as usize - x);
+        let stride = self.width as usize * 4;
+        let src_stride = image.width as usize * 4;
+
+        for y in 0..(image.height.min(self.height.saturating_sub(pos.1))) as usize {
+            let dst = (pos.1 as usize + y) * stride + x * 4;
+            let src = y * src_stride;
+            self.pixels[dst..dst + width * 4].copy_from_slice(&image.pixels[src..src + width * 4]);
+        }
+
+        Ok(())
+    }
+
+    pub fn resize(&mut self, width: usize, height: usize) {
+        if width == 0 || height == 0 || self.width == 0 || self.height == 0 {
+            return;
+        }
+
+        // Resample with premultiplied alpha to not bleed the color of transparent pixels.
+        let pixels = self
+            .pixels
+            .chunks_exact(4)
+            .map(|p| {
+                let a = p[3] as f32 / 255.0;
+                [
+                    p[0] as f32 * a,
+                    p[1] as f32 * a,
+                    p[2] as f32 * a,
+                    p[3] as f32,
+                ]
+            })
+            .collect::<Vec<_>>();
+
+        let (w, h) = (self.width as usize, self.height as usize);
+        let pixels = resize_rows(&pixels, w, h, width);
+        let pixels = resize_rows(&pixels, h, width, height);
+
+        self.width = width as u32;
+        self.height = height as u32;
+        self.pixels = pixels
+            .into_iter()
+            .flat_map(|[r, g, b, a]| {
+                let f = if a > 0.0 { 255.0 / a } else { 0.0 };
+                [r * f, g * f, b * f, a].map(|c| c.round().clamp(0.0, 255.0) as u8)
+            })
+            .collect();
+    }
+
+    /// Encodes the image, only PNG and lossless WebP are supported.
+    pub fn encode(&self, format: OutputFormat) -> Result<Vec<u8>, ImageError> {
+        let mut blob = Vec::new();
+        match format {
+            OutputFormat::Png => {
+                let mut encoder = png::Encoder::new(&mut blob, self.width, self.height);
+                encoder.set_color(png::ColorType::Rgba);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder.write_header()?.write_image_data(&self.pixels)?;
+            }
+            OutputFormat::WebpLossless => {
+                image_webp::WebPEncoder::new(&mut blob).encode(
+                    &self.pixels,
+                    self.width,
+                    self.height,
+                    image_webp::ColorType::Rgba8,
+                )?;
+            }
+            format => return Err(ImageError::UnsupportedFormat(format.to_string())),
+        }
+        Ok(blob)
+    }
+
+    /// Copies the region at `pos` of `size`, clamped to the bounds of the image.
+    fn cropped(&self, pos: (u32, u32), size: (u32, u32)) -> Result<Self, ImageError> {
+        let x0 = pos.0.min(self.width);
+        let y0 = pos.1.min(self.height);
+        let x1 = pos.0.saturating_add(size.0).min(self.width);
+        let y1 = pos.1.saturating_add(size.1).min(self.height);
+
+        if x0 >= x1 || y0 >= y1 {
+            return Err(ImageError::InvalidCrop { pos, size });
+        }
+
+        let stride = self.width as usize * 4;
+        let pixels = (y0 as usize..y1 as usize)
+            .flat_map(|y| &self.pixels[y * stride + x0 as usize * 4..y * stride + x1 as usize * 4])
+            .copied()
+            .collect();
+
+        Ok(Self {
+            width: x1 - x0,
+            height: y1 - y0,
+            pixels,
+        })
+    }
+
+    /// Multiplies the colors with the colors of `other` weighted by its alpha, both aligned
+    /// at the top left corner. The alpha of this image is kept.
+    fn multiply(&mut self, other: &Self) {
+        let width = self.width.min(other.width) as usize;
+        let height = self.height.min(other.height) as usize;
+
+        for y in 0..height {
+            for x in 0..width {
+                let top = (y * self.width as usize + x) * 4;
+                let bottom = (y * other.width as usize + x) * 4;
+
+                let top = &mut self.pixels[top..top + 4];
+                let bottom = &other.pixels[bottom..bottom + 4];
+
+                let ba = bottom[3] as f32 / 255.0;
+                for c in 0..3 {
+                    let factor = 1.0 - ba + bottom[c] as f32 / 255.0 * ba;
+                    top[c] = (top[c] as f32 * factor).round() as u8;
+                }
+            }
+        }
+    }
+
+    /// Composes this image over `other`, both aligned at the top left corner.
+    fn compose_over(&mut self, other: &Self) {
+        let width = self.width.min(other.width) as usize;
+        let height = self.height.min(other.height) as usize;
+
+        for y in 0..height {
+            for x in 0..width {
+                let top = (y * self.width as usize + x) * 4;
+                let bottom = (y * other.width as usize + x) * 4;
+
+                let top = &mut self.pixels[top..top + 4];
+                let bottom = &other.pixels[bottom..bottom + 4];
+
+                let ta = top[3] as f32 / 255.0;
+                let ba = bottom[3] as f32 / 255.0 * (1.0 - ta);
+                let a = ta + ba;
+                if a <= 0.0 {
+                    continue;
+                }
+
+                for c in 0..3 {
+                    top[c] = ((top[c] as f32 * ta + bottom[c] as f32 * ba) / a).round() as u8;
+                }
+                top[3] = (a * 255.0).round() as u8;
+            }
+        }
+    }
+}
+
+impl TryFrom<&[u8]> for Dds {
+    type Error = ImageError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let (width, height, pixels) = dds::decode(value)?;
+
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+}
+
+/// Resamples every row of the image to `new_width` pixels using a Lanczos filter.
+///
+/// The result is transposed, resizing twice yields an image in the original orientation.
+fn resize_rows(
+    pixels: &[[f32; 4]],
+    width: usize,
+    height: usize,
+    new_width: usize,
+) -> Vec<[f32; 4]> {
+    const LOBES: f32 = 3.0;
+
+    let scale = width as f32 / new_width as f32;
+    // Widen the filter when downsampling to avoid aliasing.
+    let filter_scale = scale.max(1.0);
+    let support = LOBES * filter_scale;
+
+    let mut out = vec![[0.0; 4]; new_width * height];
+    for x in 0..new_width {
+        let center = (x as f32 + 0.5) * scale;
+        let start = (center - support).floor().max(0.0) as usize;
+        let end = ((center + support).ceil() as usize).clamp(start + 1, width);
+
+        let weights = (start..end)
+            .map(|i| (i, lanczos((i as f32 + 0.5 - center) / filter_scale, LOBES)))
+            .collect::<Vec<_>>();
+        let total = weights.iter().map(|(_, w)| w).sum::<f32>();
+
+        for y in 0..height {
+            let mut pixel = [0.0; 4];
+            for &(i, w) in &weights {
+                let src = pixels[y * width + i];
+                for c in 0..4 {
+                    pixel[c] += src[c] * w;
+                }
+            }
+
+            out[x * height + y] = match total.abs() > f32::EPSILON {
+                true => pixel.map(|c| (c / total).max(0.0)),
+                false => pixels[y * width + start.min(width - 1)],
+            };
+        }
+    }
+
+    out
+}
+
+fn lanczos(x: f32, lobes: f32) -> f32 {
+    let sinc = |x: f32| match x.abs() < f32::EPSILON {
+        true => 1.0,
+        false => {
+            let x = x * std::f32::consts::PI;
+            x.sin() / x
+        }
+    };
+
+    match x.abs() < lobes {
+        true => sinc(x) * sinc(x / lobes),
+        false => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GEM: [u8; 4] = [200, 100, 50, 255];
+    const SHADING: [u8; 4] = [128, 255, 0, 255];
+    const BACKGROUND: [u8; 4] = [0, 0, 90, 255];
+
+    fn pixel(image: &Dds, x: u32, y: u32) -> [u8; 4] {
+        let i = (y * image.width + x) as usize * 4;
+        image.pixels[i..i + 4].try_into().unwrap()
+    }
+
+    /// Layered gem texture of three 6x4 layers, the gem covers the center of the first
+    /// layer, the shading the left half of the second layer and the background the whole
+    /// third layer.
+    fn layered() -> Dds {
+        let mut image = Dds::new(18, 4).unwrap();
+        for y in 0..4 {
+            for x in 0..6 {
+                let i = (y * 18 + x) * 4;
+                if (1..3).contains(&x) && (1..3).contains(&y) {
+                    image.pixels[i..i + 4].copy_from_slice(&GEM);
+                }
+                if x < 2 {
+                    image.pixels[i + 24..i + 28].copy_from_slice(&SHADING);
+                }
+                image.pixels[i + 48..i + 52].copy_from_slice(&BACKGROUND);
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn gem() {
+        let mut image = layered();
+        image.gem().unwrap();
+
+        assert_eq!((image.width, image.height), (6, 4));
+        assert_eq!(pixel(&image, 1, 1), GEM);
+        assert_eq!(pixel(&image, 2, 2), GEM);
+        assert_eq!(pixel(&image, 0, 0), BACKGROUND);
+        assert_eq!(pixel(&image, 3, 1), BACKGROUND);
+    }
+
+    #[test]
+    fn transfigured_gem() {
+        let mut image = layered();
+        image.transfigured_gem().unwrap();
+
+        assert_eq!((image.width, image.height), (6, 4));
+        // Shaded, `200 * 128 / 255`, `100 * 255 / 255` and `50 * 0 / 255`.
+        assert_eq!(pixel(&image, 1, 1), [100, 100, 0, 255]);
+        assert_eq!(pixel(&image, 1, 2), [100, 100, 0, 255]);
+        // Not covered by the shading.
+        assert_eq!(pixel(&image, 2, 1), GEM);
+        // The shading does not apply to the background.
+        assert_eq!(pixel(&image, 0, 0), BACKGROUND);
+        assert_eq!(pixel(&image, 5, 3), BACKGROUND);
+    }
+
+    #[test]
+    fn transparent_shading() {
+        let mut image = layered();
+        for y in 0..4 {
+            let i = (y * 18 + 7) * 4;
+            image.pixels[i + 3] = 0;
+        }
+        image.transfigured_gem().unwrap();
+
+        assert_eq!(pixel(&image, 1, 1), GEM);
+        assert_eq!(pixel(&image, 1, 2), GEM);
+    }
+
+    #[test]
+    fn single_layer_gem() {
+        let mut image = layered();
+        image.crop((0, 0), (6, 4)).unwrap();
+        let pixels = image.pixels.clone();
+
+        image.gem().unwrap();
+        assert_eq!(image.pixels, pixels);
+        image.transfigured_gem().unwrap();
+        assert_eq!(image.pixels, pixels);
+    }
+}
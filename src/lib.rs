@@ -1,3 +1,12 @@
+#[cfg(all(
+    feature = "pipeline",
+    not(any(feature = "magick", feature = "native-dds"))
+))]
+compile_error!("the `pipeline` feature requires either the `magick` or `native-dds` feature");
+
+// Allows `#[derive(Row)]` to refer to this crate as `::pobbin_assets`.
+extern crate self as pobbin_assets;
+
 mod bundle;
 mod dat;
 #[cfg(feature = "data")]
@@ -14,10 +23,16 @@ mod utils;
 pub use self::bundle::*;
 pub use self::dat::*;
 pub use self::hash::HashStrategy;
+#[cfg(all(feature = "pipeline", feature = "native-dds"))]
+pub use self::image::DdsError;
 #[cfg(feature = "pipeline")]
-pub use self::image::{Dds as Image, ImageError};
+pub use self::image::{Dds as Image, ImageError, OutputFormat};
 #[cfg(feature = "pipeline")]
-pub use self::pipeline::{File, Kind, Pipeline};
+pub use self::pipeline::{
+    matcher, AtlasOptions, CollisionPolicy, Config, ConfigError, File, Kind, Matcher, OutputSize,
+    Pipeline, PlannedOutput, Postprocess,
+};
 #[cfg(feature = "web")]
 pub use self::utils::latest_patch_version;
 pub use self::utils::{filepath_hash, Fnv1a64};
+pub use pobbin_assets_derive::Row;
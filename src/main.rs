@@ -1,5 +1,3 @@
-use std::borrow::Cow;
-
 use bpaf::Bpaf;
 use sha2::{Digest, Sha256};
 
@@ -12,6 +10,10 @@ struct Args {
     #[bpaf(external, optional)]
     cache: Option<Cache>,
 
+    /// Cache up to MB of decompressed bundle chunks in memory.
+    #[bpaf(argument("MB"), optional)]
+    chunk_cache_mb: Option<usize>,
+
     #[bpaf(external)]
     action: Action,
 }
@@ -33,6 +35,11 @@ enum Fs {
         #[bpaf(argument("PATH"))]
         path: String,
     },
+    Ggpk {
+        /// Local path to the `Content.ggpk` of the standalone client.
+        #[bpaf(argument("PATH"))]
+        ggpk: std::path::PathBuf,
+    },
 }
 
 #[derive(Debug, Clone, Bpaf)]
@@ -60,6 +67,16 @@ enum Action {
         /// Output directory.
         #[bpaf(short('o'), argument("PATH"), fallback("./out".into()))]
         out: std::path::PathBuf,
+        /// Amount of worker threads, defaults to the available parallelism.
+        #[bpaf(short('j'), argument("N"), optional)]
+        workers: Option<usize>,
+        /// Only regenerate files whose sources changed since the last run.
+        incremental: bool,
+        /// Pipeline config (TOML or JSON), defaults to the bundled `assets.toml`.
+        #[bpaf(argument("PATH"), optional)]
+        config: Option<std::path::PathBuf>,
+        /// Print the planned outputs without reading or writing any textures.
+        dry_run: bool,
     },
     /// Runs the data extraction pipeline.
     #[bpaf(command)]
@@ -75,16 +92,17 @@ fn main() -> anyhow::Result<()> {
 
     tracing_subscriber::fmt::init();
 
-    let fs: Box<dyn pobbin_assets::BundleFs> = match args.fs {
+    let fs: Box<dyn pobbin_assets::BundleFs + Send + Sync> = match args.fs {
         Some(Fs::Patch { patch }) => Box::new(pobbin_assets::WebBundleFs::cdn(&patch)),
         Some(Fs::Web { web }) => Box::new(pobbin_assets::WebBundleFs::new(web)),
         Some(Fs::Local { path }) => Box::new(pobbin_assets::LocalBundleFs::new(path)),
+        Some(Fs::Ggpk { ggpk }) => Box::new(pobbin_assets::GgpkBundleFs::open(ggpk)?),
         None => Box::new(pobbin_assets::WebBundleFs::cdn(
             &pobbin_assets::latest_patch_version()?,
         )),
     };
 
-    let fs: Box<dyn pobbin_assets::BundleFs> = match args.cache {
+    let fs: Box<dyn pobbin_assets::BundleFs + Send + Sync> = match args.cache {
         Some(Cache::InMemoryCache) => Box::new(pobbin_assets::CacheBundleFs::new(
             fs,
             pobbin_assets::InMemoryCache::new(),
@@ -96,11 +114,21 @@ fn main() -> anyhow::Result<()> {
         None => fs,
     };
 
+    let chunk_cache = args
+        .chunk_cache_mb
+        .map(|mb| std::sync::Arc::new(pobbin_assets::ChunkCache::new(mb * 1024 * 1024)));
+
     match args.action {
         Action::Sha(file) => sha(fs, &file),
         Action::Extract(file) => extract(fs, &file),
-        Action::Assets { out } => assets(fs, out),
-        Action::Data { out } => data(fs, out),
+        Action::Assets {
+            out,
+            workers,
+            incremental,
+            config,
+            dry_run,
+        } => assets(fs, chunk_cache, out, workers, incremental, config, dry_run),
+        Action::Data { out } => data(fs, chunk_cache, out),
     }
 }
 
@@ -136,13 +164,23 @@ fn extract<F: pobbin_assets::BundleFs>(fs: F, file: &str) -> anyhow::Result<()>
     Ok(())
 }
 
-fn assets<F: pobbin_assets::BundleFs>(fs: F, out: std::path::PathBuf) -> anyhow::Result<()> {
-    use pobbin_assets::{File, Image, Kind};
-
-    if !out.is_dir() {
+fn assets<F: pobbin_assets::BundleFs + Sync>(
+    fs: F,
+    chunk_cache: Option<std::sync::Arc<pobbin_assets::ChunkCache>>,
+    out: std::path::PathBuf,
+    workers: Option<usize>,
+    incremental: bool,
+    config: Option<std::path::PathBuf>,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    if !dry_run && !out.is_dir() {
         anyhow::bail!("out path '{}' is not a directory", out.display());
     }
 
+    let workers = workers.unwrap_or_else(|| {
+        std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get)
+    });
+
     let progress = indicatif::ProgressBar::new_spinner().with_style(
         indicatif::ProgressStyle::default_spinner().tick_strings(&[
             "▹▹▹▹▹",
@@ -155,112 +193,66 @@ fn assets<F: pobbin_assets::BundleFs>(fs: F, out: std::path::PathBuf) -> anyhow:
         ]),
     );
 
-    fn pob_item_name<'a>(file: &'a File<'a>) -> Option<Cow<'a, str>> {
-        let needs_replacement = file.name.contains(|c| matches!(c, '’' | 'ö'));
-        if needs_replacement {
-            let new_name = file
-                .name
-                .chars()
-                .map(|c| match c {
-                    '’' => '\'',
-                    'ö' => 'o',
-                    c => c,
-                })
-                .collect();
-
-            return Some(Cow::Owned(new_name));
-        }
-
-        None
-    }
+    let config = match config {
+        Some(path) => pobbin_assets::Config::load(path)?,
+        None => pobbin_assets::Config::from_toml(include_str!("../assets.toml"))?,
+    };
 
-    #[rustfmt::skip]
-    pobbin_assets::Pipeline::new(fs, out)
+    let mut pipeline = pobbin_assets::Pipeline::new(fs, out);
+    pipeline
         .progress(move |total, name| {
             if total % 10 == 0 {
                 progress.inc(1);
             }
             progress.set_message(format!("{total} / {name}"));
         })
-        .font("Art/2DArt/Fonts/Fontin-SmallCaps.ttf")
-        .select(|file: &File| file.id.starts_with("Metadata/Items/Gems"))
-        .select(|file: &File| file.id.starts_with("Metadata/Items/Belts"))
-        .select(|file: &File| file.id.starts_with("Metadata/Items/Rings"))
-        .select(|file: &File| file.id.starts_with("Metadata/Items/Flasks"))
-        .select(|file: &File| file.id.starts_with("Metadata/Items/Amulet"))
-        .select(|file: &File| file.id.starts_with("Metadata/Items/Amulets"))
-        .select(|file: &File| file.id.starts_with("Metadata/Items/Armours"))
-        .select(|file: &File| file.id.starts_with("Metadata/Items/Jewels"))
-        .select(|file: &File| file.id.starts_with("Metadata/Items/Quivers"))
-        .select(|file: &File| file.id.starts_with("Metadata/Items/Weapons"))
-        .select(|file: &File| file.id.starts_with("Metadata/Items/Trinkets"))
-        .select(|file: &File| file.id.starts_with("Metadata/Items/AnimalCharms"))
-        .select(|file: &File| file.id.starts_with("Metadata/Items/Tinctures"))
-        .select(|file: &File| file.kind == Kind::Unique)
-        .select(|file: &File| {
-            file.id
-                .starts_with("Art/2DArt/UIImages/InGame/AncestralTrial/PassiveTreeTattoos")
-        })
-        .select(|file: &File| file.id.starts_with("Art/2DArt/UIImages/InGame/ItemsHeader"))
-        .select(|file: &File| 
-                file.id.starts_with("Art/2DArt/UIImages/InGame/NormalPassive")
-                || file.id.starts_with("Art/2DArt/UIImages/InGame/NotablePassive")
-                || file.id.starts_with("Art/2DArt/UIImages/InGame/AscendancyPassive")
-                || file.id.starts_with("Art/2DArt/UIImages/InGame/KeystonePassive")
-                || file.id.starts_with("Art/2DArt/UIImages/InGame/JewelPassive")
-                || file.id.starts_with("Art/2DArt/UIImages/InGame/PassiveMastery/MasteryPassiveHeader")
-        )
-        .select(|file: &File| file.id.starts_with("Art/2DArt/UIImages/Common/IconDex"))
-        .select(|file: &File| file.id.starts_with("Art/2DArt/UIImages/Common/IconInt"))
-        .select(|file: &File| file.id.starts_with("Art/2DArt/UIImages/Common/IconStr"))
-        .select(|file: &File| {
-            file.id.starts_with("art/2dart/skillicons/passives/")
-                && file.id.ends_with("dds")
-                && !file.id.contains("/4k/")
-        })
-        .select(|file: &File| {
-            file.id
-                .starts_with("Art/2DArt/UIImages/InGame/ItemsSeparator")
-        })
-        .select(|file: &File| {
-            file.id.starts_with("Art/2DArt/UIImages/InGame/") && file.id.ends_with("ItemSymbol")
-        })
-        .rename(pob_item_name)
-        .rename(|file| file.id.ends_with("BootsAtlas1").then_some("TwoTonedEvEs").map(Into::into))
-        .rename(|file| file.id.ends_with("BootsAtlas2").then_some("TwoTonedArEv").map(Into::into))
-        .rename(|file| file.id.ends_with("BootsAtlas3").then_some("TwoTonedArEs").map(Into::into))
-        .rename(|file| file.id.ends_with("Rings/Ring12").then_some("TwoStoneFL").map(Into::into))
-        .rename(|file| file.id.ends_with("Rings/Ring13").then_some("TwoStoneCL").map(Into::into))
-        .rename(|file| file.id.ends_with("Rings/Ring14").then_some("TwoStoneFC").map(Into::into))
-        .rename(|file| file.id.starts_with("Metadata/Items/Gems").then_some(file.name.as_ref()).map(Into::into))
-        .rename(|file| file.id.starts_with("Metadata/Items/Gems").then_some(file.id.as_ref()).map(Into::into))
-        .postprocess(
-            |file: &File| file.id.starts_with("Metadata/Items/Gems"),
-            |image: &mut Image| image.gem(),
-        )
-        .postprocess(
-            |file: &File| {
-                file.id.starts_with("Metadata/Items/Flasks") || file.id.starts_with("UniqueFlask")
-            },
-            |image: &mut Image| image.flask(),
-        )
-        .postprocess(
-            |file: &File| {
-                file.id
-                    .starts_with("art/2dart/skillicons/passives/")
-            },
-            |image: &mut Image| {
-                image.resize(32, 32);
-                Ok(())
-            }
-        )
-        .execute()?;
+        .workers(workers)
+        .incremental(incremental)
+        .config(config);
+    if let Some(chunk_cache) = chunk_cache {
+        pipeline.chunk_cache(chunk_cache);
+    }
+
+    if dry_run {
+        for output in pipeline.plan()? {
+            let path = match output.atlas {
+                Some(atlas) => format!("{atlas}:{}", output.path),
+                None => output.path,
+            };
+            println!(
+                "{path} <- {} ({}) [{}]",
+                output.source,
+                output.kind,
+                output.steps.join(", ")
+            );
+        }
+        return Ok(());
+    }
+
+    pipeline.execute()?;
 
     Ok(())
 }
 
-fn data<F: pobbin_assets::BundleFs>(fs: F, out: std::path::PathBuf) -> anyhow::Result<()> {
-    let data = pobbin_assets::data::generate(fs)?;
+fn data<F: pobbin_assets::BundleFs>(
+    fs: F,
+    chunk_cache: Option<std::sync::Arc<pobbin_assets::ChunkCache>>,
+    out: std::path::PathBuf,
+) -> anyhow::Result<()> {
+    let mut bundle = pobbin_assets::Bundle::new(fs);
+    if let Some(chunk_cache) = &chunk_cache {
+        bundle.chunk_cache(chunk_cache.clone());
+    }
+
+    let data = pobbin_assets::data::generate(&bundle)?;
+
+    if let Some(chunk_cache) = chunk_cache {
+        tracing::info!(
+            "chunk cache: {} hits, {} misses",
+            chunk_cache.hits(),
+            chunk_cache.misses()
+        );
+    }
 
     let gems = std::fs::File::create(out.join("gems.json"))?;
     serde_json::to_writer(gems, &data.gems)?;
@@ -0,0 +1,381 @@
+use std::{collections::BTreeMap, fmt::Write as _, path::Path};
+
+use serde::Serialize;
+
+use super::manifest::{self, Asset, AssetKind};
+use crate::{image, OutputFormat};
+
+/// Options of a sprite sheet, see [`super::Pipeline::atlas`].
+#[derive(Debug, Clone)]
+pub struct AtlasOptions {
+    /// Maximum width and height of a sheet, sprites are spread over multiple sheets if necessary.
+    pub max_size: (u32, u32),
+    /// Transparent pixels between sprites.
+    pub padding: u32,
+    pub format: OutputFormat,
+    /// Additionally writes a stylesheet with a class per sprite.
+    pub css: bool,
+}
+
+impl Default for AtlasOptions {
+    fn default() -> Self {
+        Self {
+            max_size: (2048, 2048),
+            padding: 1,
+            format: OutputFormat::default(),
+            css: false,
+        }
+    }
+}
+
+/// Entry of the coordinate map.
+#[derive(Debug, Serialize)]
+struct Sprite {
+    /// Path of the sheet relative to the output directory.
+    sheet: String,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+/// Position of a sprite in the packed sheets.
+#[derive(Debug, Clone, Copy)]
+struct Placement {
+    sheet: usize,
+    x: u32,
+    y: u32,
+}
+
+#[derive(Default)]
+struct Sheet {
+    width: u32,
+    height: u32,
+    shelf_x: u32,
+    shelf_y: u32,
+    shelf_height: u32,
+}
+
+/// Packs sprites into sheets on shelves, tallest sprites first.
+///
+/// Returns the sheet and position of every sprite and the size of every sheet.
+fn pack(sizes: &[(u32, u32)], options: &AtlasOptions) -> (Vec<Placement>, Vec<(u32, u32)>) {
+    let (max_width, max_height) = options.max_size;
+
+    let mut order = (0..sizes.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| (std::cmp::Reverse(sizes[i].1), std::cmp::Reverse(sizes[i].0)));
+
+    let mut placements = vec![
+        Placement {
+            sheet: 0,
+            x: 0,
+            y: 0
+        };
+        sizes.len()
+    ];
+    let mut sheets = Vec::<Sheet>::new();
+    let mut oversized = Vec::new();
+
+    for i in order {
+        let (width, height) = sizes[i];
+
+        // Sprites larger than a sheet are placed on their own sheet.
+        if width > max_width || height > max_height {
+            oversized.push(i);
+            continue;
+        }
+
+        if let Some(sheet) = sheets.last_mut() {
+            if sheet.shelf_x + width > max_width {
+                sheet.shelf_y += sheet.shelf_height + options.padding;
+                sheet.shelf_x = 0;
+                sheet.shelf_height = 0;
+            }
+        }
+
+        match sheets.last() {
+            Some(sheet) if sheet.shelf_y + height <= max_height => {}
+            _ => sheets.push(Sheet::default()),
+        }
+        let sheet = sheets.last_mut().unwrap();
+
+        let (x, y) = (sheet.shelf_x, sheet.shelf_y);
+        sheet.shelf_x += width + options.padding;
+        sheet.shelf_height = sheet.shelf_height.max(height);
+        sheet.width = sheet.width.max(x + width);
+        sheet.height = sheet.height.max(y + height);
+
+        placements[i] = Placement {
+            sheet: sheets.len() - 1,
+            x,
+            y,
+        };
+    }
+
+    let mut sheets = sheets
+        .into_iter()
+        .map(|sheet| (sheet.width, sheet.height))
+        .collect::<Vec<_>>();
+
+    for i in oversized {
+        tracing::warn!(
+            "sprite of size {:?} exceeds the maximum sheet size",
+            sizes[i]
+        );
+        placements[i] = Placement {
+            sheet: sheets.len(),
+            x: 0,
+            y: 0,
+        };
+        sheets.push(sizes[i]);
+    }
+
+    (placements, sheets)
+}
+
+/// Packs the sprites into sheets and writes the sheets, the coordinate map and stylesheet.
+///
+/// Returns the written assets.
+pub(crate) fn write(
+    out: &Path,
+    name: &str,
+    options: &AtlasOptions,
+    mut sprites: Vec<(String, image::Dds)>,
+) -> anyhow::Result<Vec<Asset>> {
+    sprites.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let sizes = sprites
+        .iter()
+        .map(|(_, image)| (image.width(), image.height()))
+        .collect::<Vec<_>>();
+    let (placements, sheet_sizes) = pack(&sizes, options);
+
+    let mut assets = Vec::new();
+    let mut write = |path: String, data: &[u8], size: Option<(u32, u32)>| {
+        let file = out.join(&path);
+        std::fs::create_dir_all(file.parent().unwrap())?;
+        std::fs::write(file, data)?;
+
+        assets.push(Asset {
+            path,
+            kind: AssetKind::Atlas,
+            id: name.to_owned(),
+            name: name.to_owned(),
+            alternate_art: None,
+            variant: None,
+            width: size.map(|s| s.0),
+            height: size.map(|s| s.1),
+            size: data.len() as u64,
+            hash: manifest::hash(data),
+        });
+
+        Ok::<_, anyhow::Error>(())
+    };
+
+    let sheet_paths = (0..sheet_sizes.len())
+        .map(|i| format!("{name}-{i}.{}", options.format.extension()))
+        .collect::<Vec<_>>();
+
+    for (i, &(width, height)) in sheet_sizes.iter().enumerate() {
+        let mut sheet = image::Dds::new(width, height)?;
+        for ((_, image), placement) in sprites.iter().zip(&placements) {
+            if placement.sheet == i {
+                sheet.paste(image, (placement.x, placement.y))?;
+            }
+        }
+
+        let blob = sheet.encode(options.format)?;
+        write(sheet_paths[i].clone(), &blob, Some((width, height)))?;
+    }
+
+    let map = sprites
+        .iter()
+        .zip(&placements)
+        .map(|((sprite, image), placement)| {
+            let sprite_sheet = Sprite {
+                sheet: sheet_paths[placement.sheet].clone(),
+                x: placement.x,
+                y: placement.y,
+                width: image.width(),
+                height: image.height(),
+            };
+            (sprite.as_str(), sprite_sheet)
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    write(format!("{name}.json"), &serde_json::to_vec(&map)?, None)?;
+
+    if options.css {
+        let base = Path::new(name).parent().unwrap_or(Path::new(""));
+        let class_prefix = css_class(name);
+
+        let mut css = String::new();
+        for (sprite, s) in &map {
+            // The sheets are referenced relative to the stylesheet.
+            let sheet = Path::new(&s.sheet)
+                .strip_prefix(base)
+                .unwrap_or(Path::new(&s.sheet));
+            writeln!(
+                css,
+                ".{class_prefix}-{} {{ background: url(\"{}\") -{}px -{}px; width: {}px; height: {}px; }}",
+                css_class(sprite),
+                sheet.display(),
+                s.x,
+                s.y,
+                s.width,
+                s.height,
+            )?;
+        }
+
+        write(format!("{name}.css"), css.as_bytes(), None)?;
+    }
+
+    Ok(assets)
+}
+
+/// Turns a name into a valid css class name.
+fn css_class(name: &str) -> String {
+    name.chars()
+        .map(
+            |c| match c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                true => c,
+                false => '-',
+            },
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(max_size: (u32, u32), padding: u32) -> AtlasOptions {
+        AtlasOptions {
+            max_size,
+            padding,
+            ..Default::default()
+        }
+    }
+
+    /// Pseudo random sprite sizes between 1 and `max`.
+    fn sizes(count: usize, max: u32) -> Vec<(u32, u32)> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % u64::from(max)) as u32 + 1
+        };
+        (0..count).map(|_| (next(), next())).collect()
+    }
+
+    /// Asserts that all sprites are within their sheet and do not overlap including padding.
+    fn assert_valid(sizes: &[(u32, u32)], options: &AtlasOptions) {
+        let (placements, sheets) = pack(sizes, options);
+        assert_eq!(placements.len(), sizes.len());
+
+        for (i, (a, &(width, height))) in placements.iter().zip(sizes).enumerate() {
+            let (sheet_width, sheet_height) = sheets[a.sheet];
+            assert!(a.x + width <= sheet_width && a.y + height <= sheet_height);
+            if width <= options.max_size.0 && height <= options.max_size.1 {
+                assert!(sheet_width <= options.max_size.0 && sheet_height <= options.max_size.1);
+            }
+
+            for (b, &(other_width, other_height)) in placements.iter().zip(sizes).skip(i + 1) {
+                if a.sheet != b.sheet {
+                    continue;
+                }
+                let p = options.padding;
+                let separate = a.x + width + p <= b.x
+                    || b.x + other_width + p <= a.x
+                    || a.y + height + p <= b.y
+                    || b.y + other_height + p <= a.y;
+                assert!(separate, "sprites overlap: {a:?} {b:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn no_overlaps() {
+        assert_valid(&sizes(200, 64), &options((256, 256), 1));
+        assert_valid(&sizes(200, 64), &options((256, 256), 0));
+        assert_valid(&sizes(50, 200), &options((256, 256), 3));
+        assert_valid(&[(256, 256), (256, 256), (1, 1)], &options((256, 256), 1));
+    }
+
+    #[test]
+    fn spreads_over_sheets() {
+        let (placements, sheets) = pack(&[(100, 100); 5], &options((200, 200), 0));
+        assert_eq!(sheets, [(200, 200), (100, 100)]);
+        assert_eq!(placements.iter().filter(|p| p.sheet == 0).count(), 4);
+    }
+
+    #[test]
+    fn deterministic() {
+        let sizes = sizes(100, 48);
+        let options = options((256, 256), 1);
+
+        let (a, a_sheets) = pack(&sizes, &options);
+        let (b, b_sheets) = pack(&sizes, &options);
+        assert_eq!(a_sheets, b_sheets);
+        for (a, b) in a.iter().zip(&b) {
+            assert_eq!((a.sheet, a.x, a.y), (b.sheet, b.x, b.y));
+        }
+    }
+
+    #[test]
+    fn oversized_sprite() {
+        let sizes = [(10, 10), (300, 20), (20, 10)];
+        let (placements, sheets) = pack(&sizes, &options((256, 256), 1));
+
+        assert_eq!(sheets.len(), 2);
+        assert_eq!(sheets[1], (300, 20));
+        assert_eq!(
+            (placements[1].sheet, placements[1].x, placements[1].y),
+            (1, 0, 0)
+        );
+        assert_eq!(placements[0].sheet, 0);
+        assert_eq!(placements[2].sheet, 0);
+    }
+
+    #[test]
+    fn empty() {
+        let (placements, sheets) = pack(&[], &AtlasOptions::default());
+        assert!(placements.is_empty() && sheets.is_empty());
+    }
+
+    #[test]
+    fn write_is_independent_of_sprite_order() {
+        let sprites = |order: &[usize]| {
+            order
+                .iter()
+                .map(|&i| {
+                    let size = 4 + i as u32;
+                    (format!("sprite-{i}"), image::Dds::new(size, size).unwrap())
+                })
+                .collect::<Vec<_>>()
+        };
+        let options = AtlasOptions {
+            format: OutputFormat::Png,
+            css: true,
+            ..options((16, 16), 1)
+        };
+
+        let a = tempfile::tempdir().unwrap();
+        let b = tempfile::tempdir().unwrap();
+        let a_assets = write(a.path(), "atlas", &options, sprites(&[0, 1, 2, 3])).unwrap();
+        let b_assets = write(b.path(), "atlas", &options, sprites(&[3, 1, 0, 2])).unwrap();
+
+        let summary = |assets: Vec<Asset>| {
+            assets
+                .into_iter()
+                .map(|asset| (asset.path, asset.hash))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(summary(a_assets), summary(b_assets));
+
+        let map = std::fs::read_to_string(a.path().join("atlas.json")).unwrap();
+        assert!(map.contains(r#""sprite-0":{"sheet":"atlas-"#));
+        assert!(a.path().join("atlas.css").exists());
+    }
+}
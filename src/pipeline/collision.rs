@@ -0,0 +1,363 @@
+use std::collections::HashMap;
+
+use super::{output_path, Planned};
+
+/// How the pipeline resolves multiple files producing the same output.
+///
+/// Files rendering the same source texture with the same steps produce identical outputs
+/// and never collide, the last of them owns the output.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CollisionPolicy {
+    /// Fails the pipeline after reporting all collisions.
+    Error,
+    /// The first file keeps the output.
+    KeepFirst,
+    /// The last file keeps the output.
+    #[default]
+    KeepLast,
+    /// The first file keeps the output, following files append `-2`, `-3`, ... to their name.
+    Suffix,
+}
+
+/// Resolves collisions between the outputs of planned files and removes all outputs
+/// and sprites a file does not own.
+///
+/// Files are in priority order, a file collides with the files planned before it.
+pub(super) fn resolve(
+    policy: CollisionPolicy,
+    planned: &mut [(String, Planned)],
+) -> anyhow::Result<()> {
+    let mut owners = HashMap::<String, usize>::new();
+    let mut sprite_owners = HashMap::<(usize, String), usize>::new();
+    let mut collisions = 0;
+
+    for i in 0..planned.len() {
+        let mut names = planned[i]
+            .1
+            .outputs
+            .iter()
+            .map(|output| output.name.clone())
+            .collect::<Vec<_>>();
+        names.dedup();
+
+        for name in names {
+            let colliding = planned[i]
+                .1
+                .outputs
+                .iter()
+                .filter(|output| output.name == name)
+                .filter(|output| {
+                    let Some(&j) = owners.get(&output.path) else {
+                        return false;
+                    };
+                    let identical = planned[j].1.outputs.iter().any(|other| {
+                        other.path == output.path
+                            && other.size == output.size
+                            && other.format == output.format
+                    }) && same_render(&planned[j], &planned[i]);
+
+                    if !identical {
+                        report(policy, &output.path, &planned[j], &planned[i]);
+                    }
+                    !identical
+                })
+                .map(|output| output.path.clone())
+                .collect::<Vec<_>>();
+
+            collisions += colliding.len();
+
+            let outputs = planned[i]
+                .1
+                .outputs
+                .iter_mut()
+                .filter(|output| output.name == name);
+
+            match policy {
+                CollisionPolicy::Suffix if !colliding.is_empty() => {
+                    let outputs = outputs.collect::<Vec<_>>();
+                    let suffixed = (2..)
+                        .map(|n| format!("{name}-{n}"))
+                        .find(|name| {
+                            outputs.iter().all(|output| {
+                                let path = output_path(name, output.size, output.format);
+                                !owners.contains_key(&path)
+                            })
+                        })
+                        .expect("unbounded suffixes");
+
+                    for output in outputs {
+                        output.name = suffixed.clone();
+                        output.path = output_path(&suffixed, output.size, output.format);
+                        owners.insert(output.path.clone(), i);
+                    }
+                }
+                CollisionPolicy::KeepFirst => {
+                    for output in outputs.filter(|output| !colliding.contains(&output.path)) {
+                        owners.insert(output.path.clone(), i);
+                    }
+                }
+                _ => {
+                    for output in outputs {
+                        owners.insert(output.path.clone(), i);
+                    }
+                }
+            }
+        }
+
+        for s in 0..planned[i].1.sprites.len() {
+            let sprite = &planned[i].1.sprites[s];
+            let key = (sprite.atlas, sprite.name.clone());
+
+            let colliding = sprite_owners.get(&key).is_some_and(|&j| {
+                let identical = planned[j].1.sprites.iter().any(|other| {
+                    other.atlas == sprite.atlas
+                        && other.name == sprite.name
+                        && other.size == sprite.size
+                }) && same_render(&planned[j], &planned[i]);
+
+                if !identical {
+                    report(policy, &sprite.name, &planned[j], &planned[i]);
+                }
+                !identical
+            });
+
+            if colliding {
+                collisions += 1;
+            }
+
+            match policy {
+                CollisionPolicy::Suffix if colliding => {
+                    let name = (2..)
+                        .map(|n| format!("{}-{n}", key.1))
+                        .find(|name| !sprite_owners.contains_key(&(key.0, name.clone())))
+                        .expect("unbounded suffixes");
+
+                    planned[i].1.sprites[s].name = name.clone();
+                    sprite_owners.insert((key.0, name), i);
+                }
+                CollisionPolicy::KeepFirst if colliding => {}
+                _ => {
+                    sprite_owners.insert(key, i);
+                }
+            }
+        }
+    }
+
+    if policy == CollisionPolicy::Error && collisions > 0 {
+        anyhow::bail!("{collisions} outputs are produced by multiple files");
+    }
+
+    for (i, (_, planned)) in planned.iter_mut().enumerate() {
+        planned
+            .outputs
+            .retain(|output| owners.get(&output.path) == Some(&i));
+        planned
+            .sprites
+            .retain(|sprite| sprite_owners.get(&(sprite.atlas, sprite.name.clone())) == Some(&i));
+    }
+
+    Ok(())
+}
+
+/// Whether two files render the same image from the same source texture.
+fn same_render(a: &(String, Planned), b: &(String, Planned)) -> bool {
+    a.0 == b.0 && a.1.steps == b.1.steps
+}
+
+fn report(
+    policy: CollisionPolicy,
+    output: &str,
+    first: &(String, Planned),
+    second: &(String, Planned),
+) {
+    let (first_source, first) = first;
+    let (second_source, second) = second;
+
+    let message = format!(
+        "output '{output}' is produced by '{}' ({first_source}) and '{}' ({second_source})",
+        first.file.id, second.file.id
+    );
+    match policy {
+        CollisionPolicy::Error => tracing::error!("{message}"),
+        _ => tracing::warn!("{message}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::*;
+    use crate::{pipeline::PlannedSprite, File, Kind, OutputFormat, OutputSize};
+
+    const SIZES: [OutputSize; 2] = [
+        OutputSize::Original,
+        OutputSize::Density {
+            base: (32, 32),
+            density: 2,
+        },
+    ];
+
+    fn planned(source: &str, id: &'static str, names: &[&str]) -> (String, Planned) {
+        let outputs = names
+            .iter()
+            .flat_map(|name| SIZES.map(|size| (name.to_string(), size)))
+            .map(|(name, size)| super::super::Output {
+                path: output_path(&name, size, OutputFormat::Png),
+                name,
+                size,
+                format: OutputFormat::Png,
+            })
+            .collect();
+
+        let file = File {
+            kind: Kind::Base,
+            id: Cow::Borrowed(id),
+            name: Cow::Borrowed(id),
+            item_visual_identity: None,
+            alternate_art: None,
+        };
+        let planned = Planned {
+            file,
+            outputs,
+            sprites: Vec::new(),
+            steps: Vec::new(),
+        };
+        (source.to_owned(), planned)
+    }
+
+    fn sprite(source: &str, id: &'static str, name: &str) -> (String, Planned) {
+        let (source, mut planned) = planned(source, id, &[]);
+        planned.sprites.push(PlannedSprite {
+            atlas: 0,
+            name: name.to_owned(),
+            size: OutputSize::Original,
+        });
+        (source, planned)
+    }
+
+    fn paths(planned: &[(String, Planned)]) -> Vec<Vec<&str>> {
+        planned
+            .iter()
+            .map(|(_, p)| p.outputs.iter().map(|o| o.path.as_str()).collect())
+            .collect()
+    }
+
+    fn sprites(planned: &[(String, Planned)]) -> Vec<Vec<&str>> {
+        planned
+            .iter()
+            .map(|(_, p)| p.sprites.iter().map(|s| s.name.as_str()).collect())
+            .collect()
+    }
+
+    fn colliding() -> Vec<(String, Planned)> {
+        vec![
+            planned("a.dds", "A", &["Ring", "A"]),
+            planned("b.dds", "B", &["Ring"]),
+        ]
+    }
+
+    #[test]
+    fn keep_last() {
+        let mut planned = colliding();
+        resolve(CollisionPolicy::KeepLast, &mut planned).unwrap();
+        assert_eq!(
+            paths(&planned),
+            [vec!["A.png", "A@2x.png"], vec!["Ring.png", "Ring@2x.png"]]
+        );
+    }
+
+    #[test]
+    fn keep_first() {
+        let mut planned = colliding();
+        resolve(CollisionPolicy::KeepFirst, &mut planned).unwrap();
+        assert_eq!(
+            paths(&planned),
+            [vec!["Ring.png", "Ring@2x.png", "A.png", "A@2x.png"], vec![]]
+        );
+    }
+
+    #[test]
+    fn error() {
+        let mut planned = colliding();
+        let err = resolve(CollisionPolicy::Error, &mut planned).unwrap_err();
+        assert_eq!(err.to_string(), "2 outputs are produced by multiple files");
+    }
+
+    #[test]
+    fn suffix() {
+        let mut planned = colliding();
+        planned.push(self::planned("c.dds", "C", &["Ring"]));
+        planned.push(self::planned("d.dds", "D", &["Ring-2"]));
+        resolve(CollisionPolicy::Suffix, &mut planned).unwrap();
+        assert_eq!(
+            paths(&planned),
+            [
+                vec!["Ring.png", "Ring@2x.png", "A.png", "A@2x.png"],
+                vec!["Ring-2.png", "Ring-2@2x.png"],
+                vec!["Ring-3.png", "Ring-3@2x.png"],
+                vec!["Ring-2-2.png", "Ring-2-2@2x.png"],
+            ]
+        );
+        assert_eq!(planned[2].1.outputs[0].name, "Ring-3");
+    }
+
+    #[test]
+    fn no_collision_without_shared_outputs() {
+        let mut planned = vec![planned("a.dds", "A", &["A"]), planned("b.dds", "B", &["B"])];
+        resolve(CollisionPolicy::Error, &mut planned).unwrap();
+        assert_eq!(
+            paths(&planned),
+            [vec!["A.png", "A@2x.png"], vec!["B.png", "B@2x.png"]]
+        );
+    }
+
+    #[test]
+    fn identical_renders_do_not_collide() {
+        let mut planned = vec![
+            planned("a.dds", "A", &["Ring"]),
+            planned("a.dds", "B", &["Ring"]),
+        ];
+        resolve(CollisionPolicy::Error, &mut planned).unwrap();
+        assert_eq!(paths(&planned), [vec![], vec!["Ring.png", "Ring@2x.png"]]);
+    }
+
+    #[test]
+    fn different_steps_collide() {
+        let mut planned = vec![
+            planned("a.dds", "A", &["Ring"]),
+            planned("a.dds", "B", &["Ring"]),
+        ];
+        planned[1].1.steps.push("postprocess 0 Gem".to_owned());
+        assert!(resolve(CollisionPolicy::Error, &mut planned).is_err());
+    }
+
+    #[test]
+    fn sprite_policies() {
+        let files = || {
+            vec![
+                sprite("a.dds", "A", "Ring"),
+                sprite("b.dds", "B", "Ring"),
+                sprite("a.dds", "C", "Ring"),
+            ]
+        };
+
+        let mut planned = files();
+        assert!(resolve(CollisionPolicy::Error, &mut planned).is_err());
+
+        let mut planned = files();
+        resolve(CollisionPolicy::KeepFirst, &mut planned).unwrap();
+        // `C` renders the same image as `A`, the last of them owns the sprite.
+        assert_eq!(sprites(&planned), [vec![], vec![], vec!["Ring"]]);
+
+        // `C` renders the same image as `A` but collides with the owner `B`.
+        let mut planned = files();
+        resolve(CollisionPolicy::KeepLast, &mut planned).unwrap();
+        assert_eq!(sprites(&planned), [vec![], vec![], vec!["Ring"]]);
+
+        let mut planned = files();
+        resolve(CollisionPolicy::Suffix, &mut planned).unwrap();
+        assert_eq!(sprites(&planned), [vec![], vec!["Ring-2"], vec!["Ring"]]);
+    }
+}
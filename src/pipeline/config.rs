@@ -0,0 +1,627 @@
+use std::{borrow::Cow, collections::BTreeMap, path::Path, sync::Arc};
+
+use serde::Deserialize;
+
+use super::{matcher, AtlasOptions, CollisionPolicy, File, Kind, Matcher, Pipeline};
+use crate::{BundleFs, Image, ImageError, OutputFormat, OutputSize};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("invalid toml config: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("invalid json config: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("output format '{0}' is not supported by the image backend")]
+    UnsupportedFormat(OutputFormat),
+}
+
+/// Declarative description of the files processed by a [`Pipeline`].
+///
+/// Loaded from TOML or JSON and applied with [`Pipeline::config`].
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    collisions: Option<CollisionPolicy>,
+    #[serde(default)]
+    fonts: Vec<String>,
+    #[serde(default)]
+    select: Vec<Selector>,
+    #[serde(default)]
+    rename: Vec<Rename>,
+    #[serde(default)]
+    postprocess: Vec<Postprocess>,
+    #[serde(default)]
+    output: Vec<Output>,
+    #[serde(default)]
+    atlas: Vec<Atlas>,
+}
+
+impl Config {
+    /// Loads the config from a file, files with a `.json` extension are parsed as JSON,
+    /// everything else as TOML.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)?;
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Self::from_json(&content),
+            _ => Self::from_toml(&content),
+        }
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self, ConfigError> {
+        toml::from_str::<Self>(content)?.validated()
+    }
+
+    pub fn from_json(content: &str) -> Result<Self, ConfigError> {
+        serde_json::from_str::<Self>(content)?.validated()
+    }
+
+    /// Rejects everything which is valid syntax but can not be processed.
+    fn validated(self) -> Result<Self, ConfigError> {
+        let formats = self
+            .output
+            .iter()
+            .flat_map(|output| output.formats.iter().flatten())
+            .chain(self.atlas.iter().filter_map(|atlas| atlas.format.as_ref()));
+        if let Some(&format) = formats.into_iter().find(|format| !format.is_supported()) {
+            return Err(ConfigError::UnsupportedFormat(format));
+        }
+
+        Ok(self)
+    }
+}
+
+impl<F: BundleFs> Pipeline<F> {
+    /// Adds all fonts, selectors, renames, postprocesses, outputs and atlases of the config.
+    pub fn config(&mut self, config: Config) -> &mut Self {
+        if let Some(policy) = config.collisions {
+            self.collisions(policy);
+        }
+
+        for font in config.fonts {
+            self.font(font);
+        }
+
+        for selector in config.select {
+            self.select(selector);
+        }
+
+        for rename in config.rename {
+            self.rename(move |file| rename.rename(file));
+        }
+
+        for Postprocess { matcher, step } in config.postprocess {
+            self.postprocess(matcher, step);
+        }
+
+        for output in config.output {
+            if let Some(formats) = output.formats {
+                self.formats_for(output.matcher.clone(), formats);
+            }
+            if let Some(sizes) = output.sizes {
+                self.sizes_for(output.matcher, sizes);
+            }
+        }
+
+        for atlas in config.atlas {
+            let defaults = AtlasOptions::default();
+            let options = AtlasOptions {
+                max_size: atlas.max_size.unwrap_or(defaults.max_size),
+                padding: atlas.padding.unwrap_or(defaults.padding),
+                format: atlas.format.unwrap_or(defaults.format),
+                css: atlas.css,
+            };
+            self.atlas(atlas.name, atlas.matcher, options);
+        }
+
+        self
+    }
+}
+
+/// Matches files by their id, all specified conditions have to match.
+#[derive(Clone, Deserialize)]
+#[serde(try_from = "SelectorConfig")]
+struct Selector {
+    config: SelectorConfig,
+    matcher: Arc<dyn Matcher>,
+}
+
+impl Default for Selector {
+    fn default() -> Self {
+        Self {
+            config: SelectorConfig::default(),
+            matcher: Arc::new(|_: &File| true),
+        }
+    }
+}
+
+impl std::fmt::Debug for Selector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.config.fmt(f)
+    }
+}
+
+impl Matcher for Selector {
+    fn matches(&self, file: &File) -> bool {
+        self.matcher.matches(file)
+    }
+}
+
+impl TryFrom<SelectorConfig> for Selector {
+    type Error = SelectorError;
+
+    fn try_from(config: SelectorConfig) -> Result<Self, Self::Error> {
+        Ok(Self {
+            matcher: Arc::from(config.compile()?),
+            config,
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+enum SelectorError {
+    #[error(transparent)]
+    Glob(#[from] glob::PatternError),
+    #[error(transparent)]
+    Regex(#[from] regex::Error),
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SelectorConfig {
+    prefix: Option<String>,
+    suffix: Option<String>,
+    /// Glob pattern where `*` does not match path separators, use `**` to match directories.
+    glob: Option<String>,
+    regex: Option<String>,
+    kind: Option<KindSelector>,
+    /// Whether the file uses an alternate art visual identity.
+    alternate_art: Option<bool>,
+    /// Compares `prefix`, `suffix` and `glob` case-insensitively.
+    #[serde(default)]
+    ignore_case: bool,
+    /// Matches if any of the selectors match.
+    any: Option<Vec<SelectorConfig>>,
+    /// Matches if the selector does not match.
+    not: Option<Box<SelectorConfig>>,
+}
+
+impl SelectorConfig {
+    fn compile(&self) -> Result<Box<dyn Matcher>, SelectorError> {
+        let mut all: Vec<Box<dyn Matcher>> = Vec::new();
+
+        if let Some(prefix) = &self.prefix {
+            let prefix = matcher::prefix(prefix.clone());
+            all.push(match self.ignore_case {
+                true => Box::new(prefix.ignore_case()),
+                false => Box::new(prefix),
+            });
+        }
+        if let Some(suffix) = &self.suffix {
+            let suffix = matcher::suffix(suffix.clone());
+            all.push(match self.ignore_case {
+                true => Box::new(suffix.ignore_case()),
+                false => Box::new(suffix),
+            });
+        }
+        if let Some(glob) = &self.glob {
+            let glob = matcher::glob(glob)?;
+            all.push(match self.ignore_case {
+                true => Box::new(glob.ignore_case()),
+                false => Box::new(glob),
+            });
+        }
+        if let Some(regex) = &self.regex {
+            all.push(Box::new(matcher::regex(regex)?));
+        }
+        if let Some(kind) = self.kind {
+            all.push(kind.compile());
+        }
+        if let Some(alternate_art) = self.alternate_art {
+            all.push(match alternate_art {
+                true => Box::new(matcher::alternate_art()),
+                false => Box::new(matcher::alternate_art().not()),
+            });
+        }
+        if let Some(any) = &self.any {
+            let any = any
+                .iter()
+                .map(Self::compile)
+                .collect::<Result<Vec<_>, _>>()?;
+            all.push(Box::new(matcher::any(any)));
+        }
+        if let Some(not) = &self.not {
+            all.push(Box::new(not.compile()?.not()));
+        }
+
+        Ok(Box::new(matcher::all(all)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum KindSelector {
+    Art,
+    Base,
+    Unique,
+    File,
+}
+
+impl KindSelector {
+    fn compile(self) -> Box<dyn Matcher> {
+        match self {
+            Self::Art => Box::new(matcher::art()),
+            Self::Base => Box::new(matcher::kind(Kind::Base)),
+            Self::Unique => Box::new(matcher::kind(Kind::Unique)),
+            Self::File => Box::new(matcher::kind(Kind::File)),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Rename {
+    /// Files to rename, all files if not specified.
+    #[serde(rename = "match", default)]
+    matcher: Selector,
+    #[serde(flatten)]
+    to: RenameTo,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum RenameTo {
+    /// Fixed name.
+    Name(String),
+    /// Field of the file.
+    Field(Field),
+    /// Replaces all occurrences of the keys in the name with their values,
+    /// only applies if the name contains any of the keys.
+    Replace(BTreeMap<String, String>),
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Field {
+    Id,
+    Name,
+}
+
+impl Rename {
+    fn rename<'a>(&self, file: &'a File<'a>) -> Option<Cow<'a, str>> {
+        if !self.matcher.matches(file) {
+            return None;
+        }
+
+        match &self.to {
+            RenameTo::Name(name) => Some(Cow::Owned(name.clone())),
+            RenameTo::Field(Field::Id) => Some(Cow::Borrowed(file.id.as_ref())),
+            RenameTo::Field(Field::Name) => Some(Cow::Borrowed(file.name.as_ref())),
+            RenameTo::Replace(replace) => {
+                if !replace.keys().any(|from| file.name.contains(from.as_str())) {
+                    return None;
+                }
+
+                let name = replace
+                    .iter()
+                    .fold(file.name.to_string(), |name, (from, to)| {
+                        name.replace(from, to)
+                    });
+                Some(Cow::Owned(name))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Postprocess {
+    #[serde(rename = "match", default)]
+    matcher: Selector,
+    step: Step,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Step {
+    Gem,
+    TransfiguredGem,
+    Flask,
+    Resize {
+        width: u32,
+        height: u32,
+    },
+    Crop {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    },
+}
+
+impl super::Postprocess for Step {
+    fn postprocess(&self, image: &mut Image) -> Result<(), ImageError> {
+        match *self {
+            Self::Gem => image.gem(),
+            Self::TransfiguredGem => image.transfigured_gem(),
+            Self::Flask => image.flask(),
+            Self::Resize { width, height } => {
+                image.resize(width as usize, height as usize);
+                Ok(())
+            }
+            Self::Crop {
+                x,
+                y,
+                width,
+                height,
+            } => image.crop((x, y), (width, height)),
+        }
+    }
+
+    fn name(&self) -> Cow<'_, str> {
+        Cow::Owned(format!("{self:?}"))
+    }
+}
+
+/// Sprite sheet of all selected files matching the selector, see [`Pipeline::atlas`].
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Atlas {
+    name: String,
+    #[serde(rename = "match", default)]
+    matcher: Selector,
+    max_size: Option<(u32, u32)>,
+    padding: Option<u32>,
+    format: Option<OutputFormat>,
+    #[serde(default)]
+    css: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Output {
+    #[serde(rename = "match", default)]
+    matcher: Selector,
+    formats: Option<Vec<OutputFormat>>,
+    sizes: Option<Vec<OutputSize>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn atlas() {
+        let config = Config::from_toml(
+            r#"
+            [[atlas]]
+            name = "sprites/gems"
+            match = { prefix = "Metadata/Items/Gems/" }
+            max_size = [512, 256]
+            css = true
+
+            [[atlas]]
+            name = "sprites/all"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.atlas.len(), 2);
+        assert_eq!(config.atlas[0].max_size, Some((512, 256)));
+        assert!(config.atlas[0].css);
+        assert!(config.atlas[1].format.is_none());
+
+        let mut pipeline = Pipeline::new(crate::LocalBundleFs::new("."), ".");
+        pipeline.config(config);
+        let (name, _, options) = &pipeline.atlases[0];
+        assert_eq!(name, "sprites/gems");
+        assert_eq!(options.max_size, (512, 256));
+        assert_eq!(options.padding, AtlasOptions::default().padding);
+        assert!(options.css);
+    }
+
+    #[test]
+    #[cfg(not(feature = "magick"))]
+    fn unsupported_format() {
+        let config = r#"
+            [[output]]
+            formats = ["png", { webp = { quality = 80 } }]
+        "#;
+        let err = Config::from_toml(config).unwrap_err();
+        assert!(matches!(
+            err,
+            ConfigError::UnsupportedFormat(OutputFormat::Webp { quality: 80 })
+        ));
+
+        let config =
+            r#"{ "atlas": [{ "name": "sprites", "format": { "avif": { "quality": 50 } } }] }"#;
+        assert!(Config::from_json(config).is_err());
+
+        let config = r#"{ "output": [{ "formats": ["png", "webp_lossless"] }] }"#;
+        assert!(Config::from_json(config).is_ok());
+    }
+
+    fn file(kind: Kind, id: &'static str, name: &'static str) -> File<'static> {
+        File {
+            kind,
+            id: Cow::Borrowed(id),
+            name: Cow::Borrowed(name),
+            item_visual_identity: None,
+            alternate_art: None,
+        }
+    }
+
+    /// Selection of the pipeline hardcoded in `main.rs` before it was configurable.
+    fn baseline_selected(file: &File) -> bool {
+        const PREFIXES: &[&str] = &[
+            "Metadata/Items/Gems",
+            "Metadata/Items/Belts",
+            "Metadata/Items/Rings",
+            "Metadata/Items/Flasks",
+            "Metadata/Items/Amulet",
+            "Metadata/Items/Armours",
+            "Metadata/Items/Jewels",
+            "Metadata/Items/Quivers",
+            "Metadata/Items/Weapons",
+            "Metadata/Items/Trinkets",
+            "Metadata/Items/AnimalCharms",
+            "Metadata/Items/Tinctures",
+            "Art/2DArt/UIImages/InGame/AncestralTrial/PassiveTreeTattoos",
+            "Art/2DArt/UIImages/InGame/ItemsHeader",
+            "Art/2DArt/UIImages/InGame/NormalPassive",
+            "Art/2DArt/UIImages/InGame/NotablePassive",
+            "Art/2DArt/UIImages/InGame/AscendancyPassive",
+            "Art/2DArt/UIImages/InGame/KeystonePassive",
+            "Art/2DArt/UIImages/InGame/JewelPassive",
+            "Art/2DArt/UIImages/InGame/PassiveMastery/MasteryPassiveHeader",
+            "Art/2DArt/UIImages/Common/IconDex",
+            "Art/2DArt/UIImages/Common/IconInt",
+            "Art/2DArt/UIImages/Common/IconStr",
+            "Art/2DArt/UIImages/InGame/ItemsSeparator",
+        ];
+
+        PREFIXES.iter().any(|prefix| file.id.starts_with(prefix))
+            || file.kind == Kind::Unique
+            || (file.id.starts_with("art/2dart/skillicons/passives/")
+                && file.id.ends_with("dds")
+                && !file.id.contains("/4k/"))
+            || (file.id.starts_with("Art/2DArt/UIImages/InGame/")
+                && file.id.ends_with("ItemSymbol"))
+    }
+
+    /// Output names of the pipeline hardcoded in `main.rs` before it was configurable.
+    fn baseline_names(file: &File) -> Vec<String> {
+        let mut names = Vec::new();
+        if file.name.contains(['’', 'ö']) {
+            names.push(file.name.replace('’', "'").replace('ö', "o"));
+        }
+        for (suffix, name) in [
+            ("BootsAtlas1", "TwoTonedEvEs"),
+            ("BootsAtlas2", "TwoTonedArEv"),
+            ("BootsAtlas3", "TwoTonedArEs"),
+            ("Rings/Ring12", "TwoStoneFL"),
+            ("Rings/Ring13", "TwoStoneCL"),
+            ("Rings/Ring14", "TwoStoneFC"),
+        ] {
+            if file.id.ends_with(suffix) {
+                names.push(name.to_owned());
+            }
+        }
+        if file.id.starts_with("Metadata/Items/Gems") {
+            names.push(file.name.to_string());
+            names.push(file.id.to_string());
+        }
+        if names.is_empty() {
+            names.push(file.name.to_string());
+        }
+        names
+    }
+
+    #[test]
+    fn assets_toml_matches_baseline() {
+        let config = Config::from_toml(include_str!("../../assets.toml")).unwrap();
+        let mut pipeline = Pipeline::new(crate::LocalBundleFs::new("."), ".");
+        pipeline.config(config);
+
+        assert_eq!(pipeline.fonts, ["Art/2DArt/Fonts/Fontin-SmallCaps.ttf"]);
+
+        let files = [
+            file(
+                Kind::Base,
+                "Metadata/Items/Gems/SkillGemFireball",
+                "Fireball",
+            ),
+            file(
+                Kind::Base,
+                "Metadata/Items/Gems/SkillGemFireballAltX",
+                "Fireball of Doom",
+            ),
+            file(Kind::Base, "Metadata/Items/Amulets/Amulet1", "Coral Amulet"),
+            file(
+                Kind::Base,
+                "Metadata/Items/Armours/Boots/BootsAtlas1",
+                "Two-Toned Boots",
+            ),
+            file(
+                Kind::Base,
+                "Metadata/Items/Armours/Boots/BootsAtlas4",
+                "Other Boots",
+            ),
+            file(Kind::Base, "Metadata/Items/Rings/Ring12", "Two-Stone Ring"),
+            file(
+                Kind::Base,
+                "Metadata/Items/Flasks/FlaskLife1",
+                "Small Life Flask",
+            ),
+            file(
+                Kind::Base,
+                "Metadata/Items/Currency/CurrencyRerollRare",
+                "Chaos Orb",
+            ),
+            file(
+                Kind::Base,
+                "Metadata/Items/Maps/MapWorldsBeach",
+                "Beach Map",
+            ),
+            file(Kind::Unique, "UniqueFlask1", "Atziri’s Promise"),
+            file(Kind::Unique, "UniqueRing3", "Doedre’s Damning"),
+            file(Kind::Unique, "UniqueJewel7", "Inspired Learning"),
+            file(
+                Kind::Base,
+                "Metadata/Items/Jewels/JewelAbyss",
+                "Ghastly Eye Jewel ö",
+            ),
+            file(Kind::File, "art/2dart/skillicons/passives/Foo.dds", "Foo"),
+            file(
+                Kind::File,
+                "art/2dart/skillicons/passives/4k/Foo.dds",
+                "Foo",
+            ),
+            file(Kind::File, "art/2dart/skillicons/passives/Foo.png", "Foo"),
+            file(Kind::File, "Art/2DArt/SkillIcons/passives/Foo.dds", "Foo"),
+            file(
+                Kind::File,
+                "Art/2DArt/UIImages/InGame/ShaperItemSymbol",
+                "Shaper",
+            ),
+            file(
+                Kind::File,
+                "Art/2DArt/UIImages/InGame/ShaperItemSymbolX",
+                "Shaper",
+            ),
+            file(
+                Kind::File,
+                "Art/2DArt/UIImages/InGame/NotablePassiveFrame",
+                "Frame",
+            ),
+            file(Kind::File, "Art/2DArt/UIImages/Common/IconStr", "Str"),
+            file(Kind::File, "Art/2DArt/UIImages/Common/IconCha", "Cha"),
+        ];
+
+        for file in &files {
+            let selected = pipeline.selectors.iter().any(|s| s.matches(file));
+            assert_eq!(selected, baseline_selected(file), "{}", file.id);
+
+            let names = pipeline.names(file).collect::<Vec<_>>();
+            assert_eq!(names, baseline_names(file), "{}", file.id);
+        }
+
+        let steps = |id| pipeline.steps(&file(Kind::Base, id, ""));
+        assert_eq!(
+            steps("Metadata/Items/Gems/SkillGemFireball"),
+            ["postprocess 0 Gem"]
+        );
+        assert_eq!(
+            steps("Metadata/Items/Gems/SkillGemFireballAltY"),
+            ["postprocess 1 TransfiguredGem"]
+        );
+        assert_eq!(
+            steps("Metadata/Items/Flasks/FlaskLife1"),
+            ["postprocess 2 Flask"]
+        );
+        assert_eq!(steps("UniqueFlask1"), ["postprocess 3 Flask"]);
+        assert!(steps("Metadata/Items/Rings/Ring1").is_empty());
+    }
+}
@@ -0,0 +1,131 @@
+use std::{collections::BTreeMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Record of all outputs generated by a pipeline run.
+///
+/// Used by incremental runs to skip outputs whose inputs did not change
+/// and to remove outputs which no longer exist.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct Manifest {
+    /// Outputs keyed by their path relative to the output directory.
+    pub outputs: BTreeMap<String, ManifestEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct ManifestEntry {
+    #[serde(flatten)]
+    pub inputs: Inputs,
+    /// Description of the generated output.
+    pub asset: Asset,
+}
+
+/// Inputs an output was generated from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Inputs {
+    /// Path of the source file in the bundle.
+    pub source: String,
+    /// SHA-256 of the source file contents.
+    pub source_hash: String,
+    /// Processing steps applied to the source.
+    pub steps: Vec<String>,
+}
+
+/// Entry of the `assets.json` written to the output directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Asset {
+    /// Path of the output relative to the output directory.
+    pub path: String,
+    pub kind: AssetKind,
+    /// Original id of the file, see [`super::File::id`].
+    pub id: String,
+    /// Display name of the file.
+    pub name: String,
+    /// Id of the alternate art visual identity, see [`super::File::alternate_art`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alternate_art: Option<String>,
+    /// Size variant of the output, e.g. `2x` or `64w`, see [`super::OutputSize`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    /// Size of the output in bytes.
+    pub size: u64,
+    /// SHA-256 of the output contents.
+    pub hash: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum AssetKind {
+    Base,
+    Unique,
+    Art,
+    File,
+    /// Sprite sheet, coordinate map or stylesheet of an atlas.
+    Atlas,
+}
+
+impl From<&super::Kind> for AssetKind {
+    fn from(kind: &super::Kind) -> Self {
+        match kind {
+            super::Kind::Art { .. } => Self::Art,
+            super::Kind::Base => Self::Base,
+            super::Kind::Unique => Self::Unique,
+            super::Kind::File => Self::File,
+        }
+    }
+}
+
+impl Manifest {
+    const FILE: &'static str = ".manifest.json";
+    const ASSETS: &'static str = "assets.json";
+
+    /// Loads the manifest from the output directory.
+    ///
+    /// A missing or unreadable manifest results in an empty manifest.
+    pub fn load(out: &Path) -> Self {
+        let Ok(file) = std::fs::File::open(out.join(Self::FILE)) else {
+            return Self::default();
+        };
+
+        match serde_json::from_reader(std::io::BufReader::new(file)) {
+            Ok(manifest) => manifest,
+            Err(err) => {
+                tracing::warn!("ignoring invalid manifest: {err}");
+                Self::default()
+            }
+        }
+    }
+
+    pub fn store(&self, out: &Path) -> anyhow::Result<()> {
+        let file = std::fs::File::create(out.join(Self::FILE))?;
+        serde_json::to_writer(std::io::BufWriter::new(file), self)?;
+        Ok(())
+    }
+
+    /// Writes the `assets.json` listing all outputs to the output directory.
+    pub fn store_assets(&self, out: &Path) -> anyhow::Result<()> {
+        let assets = self.outputs.values().map(|entry| &entry.asset);
+        let file = std::fs::File::create(out.join(Self::ASSETS))?;
+        serde_json::to_writer(std::io::BufWriter::new(file), &assets.collect::<Vec<_>>())?;
+        Ok(())
+    }
+
+    /// Returns the previously generated asset at `path`, if it was generated
+    /// from the same inputs and still exists.
+    pub fn up_to_date(&self, out: &Path, path: &str, inputs: &Inputs) -> Option<&Asset> {
+        self.outputs
+            .get(path)
+            .filter(|entry| &entry.inputs == inputs && out.join(path).is_file())
+            .map(|entry| &entry.asset)
+    }
+}
+
+/// Hex encoded SHA-256 of the contents.
+pub(crate) fn hash(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
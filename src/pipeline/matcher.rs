@@ -0,0 +1,427 @@
+//! Matchers selecting the files processed by a [`Pipeline`](crate::Pipeline).
+//!
+//! All matchers except [`kind`], [`art`] and [`alternate_art`] match against the file id.
+//!
+//! ```no_run
+//! use pobbin_assets::matcher::{self, Matcher};
+//!
+//! let passives = matcher::prefix("art/2dart/skillicons/passives/")
+//!     .ignore_case()
+//!     .and(matcher::glob("**/4k/**").unwrap().not());
+//! ```
+
+use std::borrow::Cow;
+
+use super::{File, Kind};
+
+pub trait Matcher: Send + Sync {
+    fn matches(&self, item: &File) -> bool;
+
+    /// Matches if both matchers match.
+    fn and<M: Matcher>(self, other: M) -> And<Self, M>
+    where
+        Self: Sized,
+    {
+        And(self, other)
+    }
+
+    /// Matches if either of the matchers match.
+    fn or<M: Matcher>(self, other: M) -> Or<Self, M>
+    where
+        Self: Sized,
+    {
+        Or(self, other)
+    }
+
+    /// Inverts the matcher.
+    fn not(self) -> Not<Self>
+    where
+        Self: Sized,
+    {
+        Not(self)
+    }
+}
+
+impl<F: Fn(&File) -> bool + Send + Sync> Matcher for F {
+    fn matches(&self, item: &File) -> bool {
+        self(item)
+    }
+}
+
+impl Matcher for Box<dyn Matcher> {
+    fn matches(&self, item: &File) -> bool {
+        self.as_ref().matches(item)
+    }
+}
+
+/// Matches files whose id starts with `prefix`.
+pub fn prefix(prefix: impl Into<Cow<'static, str>>) -> Prefix {
+    Prefix {
+        prefix: prefix.into(),
+        ignore_case: false,
+    }
+}
+
+/// Matches files whose id ends with `suffix`.
+pub fn suffix(suffix: impl Into<Cow<'static, str>>) -> Suffix {
+    Suffix {
+        suffix: suffix.into(),
+        ignore_case: false,
+    }
+}
+
+/// Matches files whose id matches the glob `pattern`.
+///
+/// `*` does not match a `/`, use `**` to match any number of directories.
+pub fn glob(pattern: &str) -> Result<Glob, glob::PatternError> {
+    Ok(Glob {
+        pattern: glob::Pattern::new(pattern)?,
+        ignore_case: false,
+    })
+}
+
+/// Matches files whose id contains a match of the regular expression `pattern`.
+///
+/// Use the `(?i)` flag for a case-insensitive match.
+pub fn regex(pattern: &str) -> Result<Regex, regex::Error> {
+    regex::Regex::new(pattern).map(Regex)
+}
+
+/// Matches files of the kind `kind`.
+pub fn kind(kind: Kind) -> IsKind {
+    IsKind(kind)
+}
+
+/// Matches art files regardless of their position in the art file.
+pub fn art() -> Art {
+    Art
+}
+
+/// Matches files using an alternate art visual identity.
+pub fn alternate_art() -> AlternateArt {
+    AlternateArt
+}
+
+/// Matches if any of the matchers match.
+pub fn any<M: Matcher>(matchers: impl IntoIterator<Item = M>) -> Any<M> {
+    Any(matchers.into_iter().collect())
+}
+
+/// Matches if all of the matchers match.
+pub fn all<M: Matcher>(matchers: impl IntoIterator<Item = M>) -> All<M> {
+    All(matchers.into_iter().collect())
+}
+
+#[derive(Debug, Clone)]
+pub struct Prefix {
+    prefix: Cow<'static, str>,
+    ignore_case: bool,
+}
+
+impl Prefix {
+    /// Compares ASCII characters case-insensitively.
+    pub fn ignore_case(mut self) -> Self {
+        self.ignore_case = true;
+        self
+    }
+}
+
+impl Matcher for Prefix {
+    fn matches(&self, item: &File) -> bool {
+        match self.ignore_case {
+            true => item
+                .id
+                .as_bytes()
+                .get(..self.prefix.len())
+                .is_some_and(|p| p.eq_ignore_ascii_case(self.prefix.as_bytes())),
+            false => item.id.starts_with(self.prefix.as_ref()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Suffix {
+    suffix: Cow<'static, str>,
+    ignore_case: bool,
+}
+
+impl Suffix {
+    /// Compares ASCII characters case-insensitively.
+    pub fn ignore_case(mut self) -> Self {
+        self.ignore_case = true;
+        self
+    }
+}
+
+impl Matcher for Suffix {
+    fn matches(&self, item: &File) -> bool {
+        match self.ignore_case {
+            true => item
+                .id
+                .len()
+                .checked_sub(self.suffix.len())
+                .and_then(|start| item.id.as_bytes().get(start..))
+                .is_some_and(|s| s.eq_ignore_ascii_case(self.suffix.as_bytes())),
+            false => item.id.ends_with(self.suffix.as_ref()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Glob {
+    pattern: glob::Pattern,
+    ignore_case: bool,
+}
+
+impl Glob {
+    /// Compares ASCII characters case-insensitively.
+    pub fn ignore_case(mut self) -> Self {
+        self.ignore_case = true;
+        self
+    }
+}
+
+impl Matcher for Glob {
+    fn matches(&self, item: &File) -> bool {
+        let options = glob::MatchOptions {
+            case_sensitive: !self.ignore_case,
+            require_literal_separator: true,
+            require_literal_leading_dot: false,
+        };
+        self.pattern.matches_with(&item.id, options)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Regex(regex::Regex);
+
+impl Matcher for Regex {
+    fn matches(&self, item: &File) -> bool {
+        self.0.is_match(&item.id)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IsKind(Kind);
+
+impl Matcher for IsKind {
+    fn matches(&self, item: &File) -> bool {
+        item.kind == self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Art;
+
+impl Matcher for Art {
+    fn matches(&self, item: &File) -> bool {
+        matches!(item.kind, Kind::Art { .. })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AlternateArt;
+
+impl Matcher for AlternateArt {
+    fn matches(&self, item: &File) -> bool {
+        item.alternate_art.is_some()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct And<A, B>(A, B);
+
+impl<A: Matcher, B: Matcher> Matcher for And<A, B> {
+    fn matches(&self, item: &File) -> bool {
+        self.0.matches(item) && self.1.matches(item)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Or<A, B>(A, B);
+
+impl<A: Matcher, B: Matcher> Matcher for Or<A, B> {
+    fn matches(&self, item: &File) -> bool {
+        self.0.matches(item) || self.1.matches(item)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Not<M>(M);
+
+impl<M: Matcher> Matcher for Not<M> {
+    fn matches(&self, item: &File) -> bool {
+        !self.0.matches(item)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Any<M>(Vec<M>);
+
+impl<M: Matcher> Matcher for Any<M> {
+    fn matches(&self, item: &File) -> bool {
+        self.0.iter().any(|m| m.matches(item))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct All<M>(Vec<M>);
+
+impl<M: Matcher> Matcher for All<M> {
+    fn matches(&self, item: &File) -> bool {
+        self.0.iter().all(|m| m.matches(item))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(id: &'static str) -> File<'static> {
+        File {
+            kind: Kind::Base,
+            id: Cow::Borrowed(id),
+            name: Cow::Borrowed("Name"),
+            item_visual_identity: None,
+            alternate_art: None,
+        }
+    }
+
+    fn matches(matcher: impl Matcher, id: &'static str) -> bool {
+        matcher.matches(&file(id))
+    }
+
+    #[test]
+    fn prefix_and_suffix() {
+        assert!(matches(prefix("Art/2DArt"), "Art/2DArt/Foo.dds"));
+        assert!(!matches(prefix("art/2dart"), "Art/2DArt/Foo.dds"));
+        assert!(matches(
+            prefix("art/2dart").ignore_case(),
+            "Art/2DArt/Foo.dds"
+        ));
+        assert!(!matches(
+            prefix("Art/2DArt/Foo.dds/").ignore_case(),
+            "Art/2DArt/Foo.dds"
+        ));
+
+        assert!(matches(suffix(".dds"), "Art/Foo.dds"));
+        assert!(!matches(suffix(".DDS"), "Art/Foo.dds"));
+        assert!(matches(suffix(".DDS").ignore_case(), "Art/Foo.dds"));
+        assert!(!matches(
+            suffix("/Art/Foo.dds").ignore_case(),
+            "Art/Foo.dds"
+        ));
+        assert!(matches(suffix("").ignore_case(), ""));
+    }
+
+    #[test]
+    fn ignore_case_is_ascii_only() {
+        assert!(matches(prefix("Ö").ignore_case(), "Ö/Foo"));
+        assert!(!matches(prefix("ö").ignore_case(), "Ö/Foo"));
+        // The prefix ends within a multi-byte character of the id.
+        assert!(!matches(prefix("a\u{c3}").ignore_case(), "aé"));
+    }
+
+    #[test]
+    fn glob_star_does_not_cross_directories() {
+        let star = glob("Art/*.dds").unwrap();
+        assert!(star.matches(&file("Art/Foo.dds")));
+        assert!(!star.matches(&file("Art/Passives/Foo.dds")));
+
+        let double = glob("Art/**/*.dds").unwrap();
+        assert!(double.matches(&file("Art/Foo.dds")));
+        assert!(double.matches(&file("Art/Passives/4k/Foo.dds")));
+        assert!(!double.matches(&file("Other/Art/Foo.dds")));
+
+        let dir = glob("**/4k/**").unwrap();
+        assert!(dir.matches(&file("art/passives/4k/foo.dds")));
+        assert!(!dir.matches(&file("art/passives/4kfoo.dds")));
+    }
+
+    #[test]
+    fn glob_ignore_case() {
+        assert!(!matches(glob("art/*.dds").unwrap(), "Art/Foo.DDS"));
+        assert!(matches(
+            glob("art/*.dds").unwrap().ignore_case(),
+            "Art/Foo.DDS"
+        ));
+    }
+
+    #[test]
+    fn glob_invalid() {
+        assert!(glob("Art/***").is_err());
+        assert!(glob("Art/[").is_err());
+    }
+
+    #[test]
+    fn regex_searches_the_id() {
+        assert!(matches(
+            regex("Alt[XYZ]$").unwrap(),
+            "Metadata/Gems/FireballAltX"
+        ));
+        assert!(!matches(
+            regex("Alt[XYZ]$").unwrap(),
+            "Metadata/Gems/FireballAltXY"
+        ));
+        assert!(matches(
+            regex("(?i)gems").unwrap(),
+            "Metadata/Gems/Fireball"
+        ));
+        assert!(regex("(").is_err());
+    }
+
+    #[test]
+    fn kinds() {
+        let mut sprite = file("Art/Foo");
+        sprite.kind = Kind::Art {
+            art_file: "Art/Sheet".to_owned(),
+            position: (1, 2),
+            size: (3, 4),
+        };
+        assert!(art().matches(&sprite));
+        assert!(!art().matches(&file("Art/Foo")));
+        assert!(!kind(Kind::Unique).matches(&sprite));
+        assert!(kind(Kind::Base).matches(&file("Art/Foo")));
+
+        let mut alternate = file("Art/Foo");
+        alternate.alternate_art = Some(Cow::Borrowed("Pride"));
+        assert!(alternate_art().matches(&alternate));
+        assert!(!alternate_art().matches(&file("Art/Foo")));
+    }
+
+    #[test]
+    fn combinators() {
+        let id = "Art/Foo.dds";
+        assert!(matches(prefix("Art").and(suffix(".dds")), id));
+        assert!(!matches(prefix("Art").and(suffix(".png")), id));
+        assert!(matches(prefix("Other").or(suffix(".dds")), id));
+        assert!(!matches(prefix("Other").or(suffix(".png")), id));
+        assert!(matches(prefix("Other").not(), id));
+        assert!(!matches(prefix("Art").not(), id));
+        assert!(matches(prefix("Art").not().not(), id));
+    }
+
+    #[test]
+    fn any_and_all() {
+        let id = "Art/Foo.dds";
+        assert!(matches(any([prefix("Other"), prefix("Art")]), id));
+        assert!(!matches(any([prefix("Other"), prefix("Meta")]), id));
+        assert!(!matches(any(Vec::<Prefix>::new()), id));
+
+        assert!(matches(all([prefix("Art"), prefix("Art/F")]), id));
+        assert!(!matches(all([prefix("Art"), prefix("Other")]), id));
+        assert!(matches(all(Vec::<Prefix>::new()), id));
+
+        let boxed: Vec<Box<dyn Matcher>> = vec![Box::new(prefix("Art")), Box::new(suffix(".png"))];
+        assert!(matches(any(boxed).not().or(suffix(".dds")), id));
+    }
+
+    #[test]
+    fn closures() {
+        assert!(matches(|f: &File| f.id.len() == 3, "Art"));
+        assert!(matches(
+            (|f: &File| f.id.len() == 3).and(prefix("A")),
+            "Art"
+        ));
+    }
+}
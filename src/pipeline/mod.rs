@@ -0,0 +1,1537 @@
+mod atlas;
+mod collision;
+mod config;
+mod manifest;
+pub mod matcher;
+
+use std::{
+    borrow::Cow,
+    collections::{hash_map::Entry, BTreeMap, HashMap},
+    io::Write,
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::Receiver,
+        Arc, Mutex,
+    },
+};
+
+use anyhow::Context as _;
+
+pub use self::atlas::AtlasOptions;
+pub use self::collision::CollisionPolicy;
+pub use self::config::{Config, ConfigError};
+use self::manifest::{Asset, AssetKind, Inputs, Manifest, ManifestEntry};
+pub use self::matcher::Matcher;
+use crate::{
+    image, BaseItemTypes, Bundle, BundleFs, BundleResult, ChunkCache, DatFile, DatString,
+    ForeignKey, Image, ImageError, IndexBundle, ItemVisualIdentity, OutputFormat,
+    UniqueStashLayout, Words,
+};
+
+type DynRenamer = dyn for<'a> Fn(&'a File<'a>) -> Option<Cow<'a, str>> + Send + Sync;
+type DynProgress = dyn Fn(usize, &str) + Send + Sync;
+/// A source texture with all files rendered from it.
+type Job = (String, Vec<u8>, Vec<Planned>);
+
+/// A file rendered from a source texture into one or more outputs.
+#[derive(Debug)]
+struct Planned {
+    file: File<'static>,
+    /// Outputs owned by this file.
+    outputs: Vec<Output>,
+    /// Sprites owned by this file, files packed into a sprite sheet have no other outputs.
+    sprites: Vec<PlannedSprite>,
+    /// Processing steps applied to the source texture.
+    steps: Vec<String>,
+}
+
+#[derive(Debug)]
+struct PlannedSprite {
+    /// Index of the atlas in [`Pipeline::atlases`].
+    atlas: usize,
+    name: String,
+    size: OutputSize,
+}
+
+#[derive(Debug)]
+struct Output {
+    /// Name of the output, the path without the size suffix and extension.
+    name: String,
+    /// Path relative to the output directory.
+    path: String,
+    size: OutputSize,
+    format: OutputFormat,
+}
+
+pub struct Pipeline<F: BundleFs> {
+    fs: F,
+    out: PathBuf,
+    progress: Box<DynProgress>,
+    selectors: Vec<Box<dyn Matcher>>,
+    postprocess: Vec<(Box<dyn Matcher>, Box<dyn Postprocess>)>,
+    rename: Vec<Box<DynRenamer>>,
+    formats: Vec<OutputFormat>,
+    format_overrides: Vec<(Box<dyn Matcher>, Vec<OutputFormat>)>,
+    sizes: Vec<OutputSize>,
+    size_overrides: Vec<(Box<dyn Matcher>, Vec<OutputSize>)>,
+    atlases: Vec<(String, Box<dyn Matcher>, AtlasOptions)>,
+    collisions: CollisionPolicy,
+    fonts: Vec<String>,
+    workers: usize,
+    incremental: bool,
+    chunk_cache: Option<Arc<ChunkCache>>,
+}
+
+impl<F: BundleFs> Pipeline<F> {
+    pub fn new(fs: F, out: impl Into<PathBuf>) -> Self {
+        Self {
+            fs,
+            out: out.into(),
+            progress: Box::new(|_, _| {}),
+            selectors: Vec::new(),
+            postprocess: Vec::new(),
+            rename: Vec::new(),
+            formats: vec![OutputFormat::default()],
+            format_overrides: Vec::new(),
+            sizes: vec![OutputSize::Original],
+            size_overrides: Vec::new(),
+            atlases: Vec::new(),
+            collisions: CollisionPolicy::default(),
+            fonts: Vec::new(),
+            workers: 1,
+            incremental: false,
+            chunk_cache: None,
+        }
+    }
+
+    /// Skips outputs whose inputs did not change since the last run and removes
+    /// outputs which are no longer generated.
+    ///
+    /// Every run records its outputs in a manifest in the output directory.
+    pub fn incremental(&mut self, incremental: bool) -> &mut Self {
+        self.incremental = incremental;
+        self
+    }
+
+    /// Amount of worker threads used to decode, process and encode textures.
+    pub fn workers(&mut self, workers: usize) -> &mut Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// Caches decompressed bundle chunks, see [`Bundle::chunk_cache`].
+    pub fn chunk_cache(&mut self, cache: impl Into<Arc<ChunkCache>>) -> &mut Self {
+        self.chunk_cache = Some(cache.into());
+        self
+    }
+
+    pub fn progress(
+        &mut self,
+        progress: impl Fn(usize, &str) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.progress = Box::new(progress);
+        self
+    }
+
+    pub fn font(&mut self, font: impl Into<String>) -> &mut Self {
+        self.fonts.push(font.into());
+        self
+    }
+
+    pub fn select(&mut self, matcher: impl Matcher + 'static) -> &mut Self {
+        self.selectors.push(Box::new(matcher));
+        self
+    }
+
+    pub fn rename<T>(&mut self, renamer: T) -> &mut Self
+    where
+        T: for<'a> Fn(&'a File<'a>) -> Option<Cow<'a, str>> + Send + Sync + 'static,
+    {
+        self.rename.push(Box::new(renamer));
+        self
+    }
+
+    pub fn postprocess(
+        &mut self,
+        matcher: impl Matcher + 'static,
+        postprocess: impl Postprocess + 'static,
+    ) -> &mut Self {
+        self.postprocess
+            .push((Box::new(matcher), Box::new(postprocess)));
+        self
+    }
+
+    /// Output formats of all files, defaults to [`OutputFormat::default`].
+    ///
+    /// Every file is written once per size and format, of multiple outputs with
+    /// the same path only the first one is used.
+    pub fn formats(&mut self, formats: impl IntoIterator<Item = OutputFormat>) -> &mut Self {
+        self.formats = formats.into_iter().collect();
+        self
+    }
+
+    /// Output formats of all files matching the matcher, overrides [`Pipeline::formats`].
+    ///
+    /// The first matching override is used.
+    pub fn formats_for(
+        &mut self,
+        matcher: impl Matcher + 'static,
+        formats: impl IntoIterator<Item = OutputFormat>,
+    ) -> &mut Self {
+        self.format_overrides
+            .push((Box::new(matcher), formats.into_iter().collect()));
+        self
+    }
+
+    /// Output sizes of all files, defaults to [`OutputSize::Original`].
+    ///
+    /// All sizes are resampled from the processed source texture.
+    pub fn sizes(&mut self, sizes: impl IntoIterator<Item = OutputSize>) -> &mut Self {
+        self.sizes = sizes.into_iter().collect();
+        self
+    }
+
+    /// Output sizes of all files matching the matcher, overrides [`Pipeline::sizes`].
+    ///
+    /// The first matching override is used.
+    pub fn sizes_for(
+        &mut self,
+        matcher: impl Matcher + 'static,
+        sizes: impl IntoIterator<Item = OutputSize>,
+    ) -> &mut Self {
+        self.size_overrides
+            .push((Box::new(matcher), sizes.into_iter().collect()));
+        self
+    }
+
+    /// Packs all selected files matching the matcher into sprite sheets instead
+    /// of writing them individually.
+    ///
+    /// Writes the sheets as `{name}-{n}.{ext}`, a `{name}.json` with the position of every
+    /// sprite keyed by its output name and optionally a `{name}.css`. Sprites use the
+    /// first configured size of the file.
+    pub fn atlas(
+        &mut self,
+        name: impl Into<String>,
+        matcher: impl Matcher + 'static,
+        options: AtlasOptions,
+    ) -> &mut Self {
+        self.atlases.push((name.into(), Box::new(matcher), options));
+        self
+    }
+
+    /// How outputs produced by multiple files are resolved, all collisions are reported.
+    ///
+    /// Files are ordered as they are discovered, base items and uniques are followed by
+    /// the UI images and bundle files. The last file wins by default.
+    pub fn collisions(&mut self, policy: CollisionPolicy) -> &mut Self {
+        self.collisions = policy;
+        self
+    }
+
+    /// Runs the pipeline and writes an `assets.json` describing all outputs
+    /// to the output directory.
+    pub fn execute(&self) -> anyhow::Result<()>
+    where
+        F: Sync,
+    {
+        self.check_formats()?;
+
+        let bundle = self.bundle();
+        let index = bundle.index()?;
+
+        let mut sources = self.planned(&index)?;
+
+        let total = AtomicUsize::new(0);
+        let increment = |name: &str| {
+            let total = total.fetch_add(1, Ordering::Relaxed) + 1;
+            (self.progress)(total, name);
+        };
+
+        let previous = match self.incremental {
+            true => Manifest::load(&self.out),
+            false => Manifest::default(),
+        };
+        let manifest = Mutex::new(Manifest::default());
+
+        // Sprites are processed on this thread, images can not be sent between threads.
+        let mut sprites = vec![Vec::new(); self.atlases.len()];
+
+        let failed = AtomicBool::new(false);
+        let (tx, rx) = std::sync::mpsc::sync_channel(self.workers * 2);
+        // Shared between the workers, the receiver is dropped once all workers exited.
+        let rx = Arc::new(Mutex::new(rx));
+
+        std::thread::scope(|scope| {
+            let (previous, manifest, failed, increment) =
+                (&previous, &manifest, &failed, &increment);
+
+            let workers = (0..self.workers)
+                .map(|_| {
+                    let jobs = Arc::clone(&rx);
+                    scope.spawn(move || {
+                        let result = self.worker(&jobs, previous, manifest, increment);
+                        if result.is_err() {
+                            failed.store(true, Ordering::Relaxed);
+                        }
+                        result
+                    })
+                })
+                .collect::<Vec<_>>();
+            drop(rx);
+
+            // Bundles are read and decompressed in order on this thread,
+            // processing of the textures happens concurrently on the workers.
+            let mut produced: anyhow::Result<()> = Ok(());
+            for dds in index.read_many(sources.keys().cloned().collect::<Vec<_>>()) {
+                if failed.load(Ordering::Relaxed) {
+                    break;
+                }
+
+                let (source, dds) = match dds {
+                    Ok(dds) => dds,
+                    Err(err) => {
+                        produced = Err(err.into());
+                        break;
+                    }
+                };
+
+                let (sprite_files, files): (Vec<_>, Vec<_>) = sources
+                    .remove(&source)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .partition(|planned| !planned.sprites.is_empty());
+
+                for planned in &sprite_files {
+                    if let Err(err) = self.sprites(&source, &dds, planned, &mut sprites, increment)
+                    {
+                        produced = Err(err);
+                        break;
+                    }
+                }
+                if produced.is_err() {
+                    break;
+                }
+
+                if !files.is_empty() && tx.send((source, dds, files)).is_err() {
+                    break;
+                }
+            }
+            drop(tx);
+
+            for worker in workers {
+                worker.join().expect("pipeline worker panicked")?;
+            }
+
+            produced
+        })?;
+
+        for source in sources.keys() {
+            tracing::warn!("dds file '{source}' does not exist");
+        }
+
+        let mut manifest = manifest.into_inner().unwrap();
+
+        for ((name, _, options), sprites) in self.atlases.iter().zip(sprites) {
+            if sprites.is_empty() {
+                continue;
+            }
+
+            let steps = vec![format!("atlas {}", sprites.len())];
+            for asset in atlas::write(&self.out, name, options, sprites)? {
+                let entry = ManifestEntry {
+                    inputs: Inputs {
+                        source: name.clone(),
+                        source_hash: asset.hash.clone(),
+                        steps: steps.clone(),
+                    },
+                    asset,
+                };
+                manifest.outputs.insert(entry.asset.path.clone(), entry);
+            }
+            tracing::debug!("generated atlas '{name}'");
+        }
+
+        for font in &self.fonts {
+            let Some(file) = index.read_by_name(font)? else {
+                tracing::warn!("font '{font}' does not exist");
+                continue;
+            };
+
+            let inputs = Inputs {
+                source: font.clone(),
+                source_hash: manifest::hash(&file),
+                steps: vec!["font".to_owned()],
+            };
+
+            let mut outputs = vec![font.clone()];
+            if font.ends_with(".ttf") {
+                let woff2 = std::path::Path::new(font).with_extension("woff2");
+                outputs.push(woff2.to_string_lossy().into_owned());
+            }
+
+            let assets = match outputs
+                .iter()
+                .map(|path| previous.up_to_date(&self.out, path, &inputs).cloned())
+                .collect::<Option<Vec<_>>>()
+            {
+                Some(assets) => {
+                    tracing::debug!("font '{font}' is up to date");
+                    assets
+                }
+                None => {
+                    self.write_font(font, &file)?;
+                    tracing::debug!("generated font '{font}'");
+
+                    outputs
+                        .into_iter()
+                        .map(|path| {
+                            let data = std::fs::read(self.out.join(&path))?;
+                            Ok(Asset {
+                                path,
+                                kind: AssetKind::File,
+                                id: font.clone(),
+                                name: font.clone(),
+                                alternate_art: None,
+                                variant: None,
+                                width: None,
+                                height: None,
+                                size: data.len() as u64,
+                                hash: manifest::hash(&data),
+                            })
+                        })
+                        .collect::<anyhow::Result<Vec<_>>>()?
+                }
+            };
+
+            for asset in assets {
+                let entry = ManifestEntry {
+                    inputs: inputs.clone(),
+                    asset,
+                };
+                manifest.outputs.insert(entry.asset.path.clone(), entry);
+            }
+            increment(font);
+        }
+
+        remove_stale(&self.out, &previous, &manifest)?;
+
+        manifest.store(&self.out)?;
+        manifest.store_assets(&self.out)?;
+
+        tracing::info!(
+            "extracted a total of {} assets",
+            total.load(Ordering::Relaxed)
+        );
+        if let Some(cache) = index.chunk_cache() {
+            tracing::info!(
+                "chunk cache: {} hits, {} misses",
+                cache.hits(),
+                cache.misses()
+            );
+        }
+
+        Ok(())
+    }
+
+    fn bundle(&self) -> Bundle<&F> {
+        let mut bundle = Bundle::new(&self.fs);
+        if let Some(cache) = &self.chunk_cache {
+            bundle.chunk_cache(Arc::clone(cache));
+        }
+        bundle
+    }
+
+    /// Resolves all files and outputs of the pipeline without reading any textures.
+    ///
+    /// Outputs are sorted by their path and followed by the sprites of each atlas,
+    /// which use the sprite name as path.
+    pub fn plan(&self) -> anyhow::Result<Vec<PlannedOutput>> {
+        self.check_formats()?;
+
+        let bundle = self.bundle();
+        let index = bundle.index()?;
+
+        let mut outputs = Vec::new();
+        for (source, files) in self.planned(&index)? {
+            for planned in files {
+                let entry =
+                    |path: String, atlas: Option<String>, steps: Vec<String>| PlannedOutput {
+                        path,
+                        atlas,
+                        source: source.clone(),
+                        kind: planned.file.kind.clone(),
+                        id: planned.file.id.to_string(),
+                        name: planned.file.name.to_string(),
+                        steps,
+                    };
+
+                for output in &planned.outputs {
+                    let steps = [
+                        &planned.steps[..],
+                        &[output.size.to_string(), output.format.to_string()],
+                    ]
+                    .concat();
+                    outputs.push(entry(output.path.clone(), None, steps));
+                }
+                for sprite in &planned.sprites {
+                    let steps = [&planned.steps[..], &[sprite.size.to_string()]].concat();
+                    let atlas = self.atlases[sprite.atlas].0.clone();
+                    outputs.push(entry(sprite.name.clone(), Some(atlas), steps));
+                }
+            }
+        }
+
+        for font in &self.fonts {
+            let mut paths = vec![font.clone()];
+            if font.ends_with(".ttf") {
+                let woff2 = std::path::Path::new(font).with_extension("woff2");
+                paths.push(woff2.to_string_lossy().into_owned());
+            }
+
+            outputs.extend(paths.into_iter().map(|path| PlannedOutput {
+                path,
+                atlas: None,
+                source: font.clone(),
+                kind: Kind::File,
+                id: font.clone(),
+                name: font.clone(),
+                steps: vec!["font".to_owned()],
+            }));
+        }
+
+        outputs.sort_by(|a, b| (&a.atlas, &a.path).cmp(&(&b.atlas, &b.path)));
+
+        Ok(outputs)
+    }
+
+    /// Resolves all selected files and their outputs grouped by their source texture.
+    ///
+    /// If multiple files produce the same output, the last one wins. Rows which fail to
+    /// parse or reference missing rows are skipped with a warning.
+    fn planned<F2: BundleFs>(
+        &self,
+        index: &IndexBundle<F2>,
+    ) -> anyhow::Result<BTreeMap<String, Vec<Planned>>> {
+        macro_rules! read {
+            ($name:ident, $type:ty) => {
+                let Some($name) = index.read::<$type>()?.transpose()? else {
+                    anyhow::bail!("{} table does not exist", stringify!($type));
+                };
+            };
+        }
+
+        read!(bases, BaseItemTypes);
+        read!(uniques, UniqueStashLayout);
+        read!(words, Words);
+        read!(vis, ItemVisualIdentity);
+
+        let mut base_files = Vec::with_capacity(bases.len());
+        for (i, base) in bases.try_iter().enumerate() {
+            match base.map_err(Into::into).and_then(base_file) {
+                Ok(file) => base_files.push(file),
+                Err(err) => tracing::warn!("skipping base item type {i}: {err:#}"),
+            }
+        }
+
+        let mut unique_files = Vec::new();
+        for (i, unique) in uniques.try_iter().enumerate() {
+            let unique = match unique {
+                Ok(unique) => unique,
+                Err(err) => {
+                    tracing::warn!("skipping unique {i}: {err}");
+                    continue;
+                }
+            };
+            if !unique.show_if_empty_challenge_league {
+                continue;
+            }
+
+            match unique_file(&unique, &words, &vis) {
+                Ok(file) => unique_files.push(file),
+                Err(err) => tracing::warn!("skipping unique {i}: {err:#}"),
+            }
+        }
+
+        let files = base_files
+            .into_iter()
+            .chain(unique_files)
+            .map(|mut file| {
+                let vis = file.item_visual_identity.and_then(|key| {
+                    key.resolve(&vis)
+                        .inspect_err(|err| {
+                            tracing::warn!("invalid visual identity of '{}': {err}", file.id)
+                        })
+                        .ok()
+                });
+                if let Some(vis) = vis.as_ref().filter(|vis| vis.is_alternate_art) {
+                    file.alternate_art = String::try_from(&vis.id).ok().map(Cow::Owned);
+                }
+                (file, vis)
+            })
+            .filter(|(f, _)| self.selectors.iter().any(|s| s.matches(f)));
+
+        // Files with their source dds file, multiple files can share the same texture.
+        // The order of the files determines which file wins if multiple files produce
+        // the same output.
+        let mut sources = Vec::new();
+        for (item, vis) in files {
+            let Some(vis) = vis else {
+                tracing::warn!("item '{item:?}' has no visual identity");
+                continue;
+            };
+
+            let Ok(dds_file) = String::try_from(&vis.dds_file) else {
+                tracing::warn!("invalid dds_file on item '{item:?}' and vis '{vis:?}'");
+                continue;
+            };
+
+            sources.push((dds_file, item));
+        }
+
+        for file in self.ui_images(index)? {
+            let Kind::Art { ref art_file, .. } = file.kind else {
+                unreachable!("ui images generated non art kind");
+            };
+
+            sources.push((art_file.clone(), file));
+        }
+
+        // TODO: this only works for dds atm, change it when necessary
+        for file in self.bundle_files(index)? {
+            let file = file?;
+            sources.push((file.id.to_string(), file));
+        }
+
+        self.plan_files(sources)
+    }
+
+    /// Plans all outputs of the files with their source texture and groups them by source.
+    ///
+    /// Collisions are resolved in the order of the files.
+    fn plan_files(
+        &self,
+        files: Vec<(String, File<'static>)>,
+    ) -> anyhow::Result<BTreeMap<String, Vec<Planned>>> {
+        let mut planned = files
+            .into_iter()
+            .map(|(source, file)| {
+                let planned = match self.atlases.iter().position(|(_, m, _)| m.matches(&file)) {
+                    Some(atlas) => Planned {
+                        outputs: Vec::new(),
+                        sprites: self
+                            .output_names(&file)
+                            .into_iter()
+                            .map(|name| PlannedSprite {
+                                atlas,
+                                name,
+                                size: self.file_sizes(&file)[0],
+                            })
+                            .collect(),
+                        steps: self.steps(&file),
+                        file,
+                    },
+                    None => Planned {
+                        outputs: self.outputs(&file),
+                        sprites: Vec::new(),
+                        steps: self.steps(&file),
+                        file,
+                    },
+                };
+                (source, planned)
+            })
+            .collect::<Vec<_>>();
+
+        collision::resolve(self.collisions, &mut planned)?;
+
+        let mut sources = BTreeMap::<_, Vec<_>>::new();
+        for (source, planned) in planned {
+            if !planned.outputs.is_empty() || !planned.sprites.is_empty() {
+                sources.entry(source).or_default().push(planned);
+            }
+        }
+
+        Ok(sources)
+    }
+
+    fn worker(
+        &self,
+        jobs: &Mutex<Receiver<Job>>,
+        previous: &Manifest,
+        manifest: &Mutex<Manifest>,
+        increment: &(dyn Fn(&str) + Sync),
+    ) -> anyhow::Result<()> {
+        loop {
+            let Ok((source, dds, files)) = jobs.lock().unwrap().recv() else {
+                return Ok(());
+            };
+
+            let source_hash = manifest::hash(&dds);
+
+            for planned in &files {
+                let inputs = planned
+                    .outputs
+                    .iter()
+                    .map(|output| Inputs {
+                        source: source.clone(),
+                        source_hash: source_hash.clone(),
+                        steps: [
+                            &planned.steps[..],
+                            &[output.size.to_string(), output.format.to_string()],
+                        ]
+                        .concat(),
+                    })
+                    .collect::<Vec<_>>();
+
+                let assets = match planned
+                    .outputs
+                    .iter()
+                    .zip(&inputs)
+                    .map(|(output, inputs)| {
+                        previous
+                            .up_to_date(&self.out, &output.path, inputs)
+                            .cloned()
+                    })
+                    .collect::<Option<Vec<_>>>()
+                {
+                    Some(assets) => {
+                        tracing::debug!("files for '{}' are up to date", planned.file.id);
+                        // The same output may now be owned by a different file.
+                        assets
+                            .into_iter()
+                            .map(|asset| Asset {
+                                kind: AssetKind::from(&planned.file.kind),
+                                id: planned.file.id.to_string(),
+                                name: planned.file.name.to_string(),
+                                alternate_art: planned
+                                    .file
+                                    .alternate_art
+                                    .as_deref()
+                                    .map(Into::into),
+                                ..asset
+                            })
+                            .collect()
+                    }
+                    None => match self.render(&source, &dds, planned)? {
+                        Some(assets) => assets,
+                        None => continue,
+                    },
+                };
+
+                let mut manifest = manifest.lock().unwrap();
+                for (asset, inputs) in assets.into_iter().zip(inputs) {
+                    let entry = ManifestEntry { inputs, asset };
+                    manifest.outputs.insert(entry.asset.path.clone(), entry);
+                }
+                drop(manifest);
+
+                for output in &planned.outputs {
+                    tracing::debug!("generated file '{}'", output.path);
+                    increment(&output.path);
+                }
+            }
+        }
+    }
+
+    /// Decodes the source texture of a file and applies all processing steps.
+    ///
+    /// Returns `None` if the source texture can not be read.
+    fn process(
+        &self,
+        source: &str,
+        dds: &[u8],
+        planned: &Planned,
+    ) -> anyhow::Result<Option<image::Dds>> {
+        let Ok(mut dds) = image::Dds::try_from(dds) else {
+            tracing::warn!("unable to read dds {source}");
+            return Ok(None);
+        };
+
+        match planned.file.kind {
+            Kind::Art { position, size, .. } => dds.crop(position, size)?,
+            _ => self.apply_postprocess(&planned.file, &mut dds)?,
+        };
+
+        Ok(Some(dds))
+    }
+
+    /// Processes a file packed into sprite sheets and collects its sprites.
+    fn sprites(
+        &self,
+        source: &str,
+        dds: &[u8],
+        planned: &Planned,
+        sprites: &mut [Vec<(String, image::Dds)>],
+        increment: &(dyn Fn(&str) + Sync),
+    ) -> anyhow::Result<()> {
+        let Some(image) = self.process(source, dds, planned)? else {
+            return Ok(());
+        };
+
+        for sprite in &planned.sprites {
+            let mut image = image.clone();
+            if let Some((width, height)) = sprite.size.dimensions(image.width(), image.height()) {
+                image.resize(width as usize, height as usize);
+            }
+            sprites[sprite.atlas].push((sprite.name.clone(), image));
+
+            tracing::debug!("generated sprite '{}'", sprite.name);
+            increment(&sprite.name);
+        }
+
+        Ok(())
+    }
+
+    /// Renders a single file from its source texture and writes all its outputs.
+    ///
+    /// Returns `None` if the source texture can not be read.
+    fn render(
+        &self,
+        source: &str,
+        dds: &[u8],
+        planned: &Planned,
+    ) -> anyhow::Result<Option<Vec<Asset>>> {
+        let Some(dds) = self.process(source, dds, planned)? else {
+            return Ok(None);
+        };
+
+        let mut resized = HashMap::new();
+        let mut encoded = HashMap::new();
+        let mut assets = Vec::with_capacity(planned.outputs.len());
+        for output in &planned.outputs {
+            let image = match output.size.dimensions(dds.width(), dds.height()) {
+                None => &dds,
+                Some((width, height)) => &*resized.entry(output.size).or_insert_with(|| {
+                    let mut image = dds.clone();
+                    image.resize(width as usize, height as usize);
+                    image
+                }),
+            };
+
+            let (blob, hash) = match encoded.entry((output.size, output.format)) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let blob = image.encode(output.format)?;
+                    let hash = manifest::hash(&blob);
+                    entry.insert((blob, hash))
+                }
+            };
+
+            self.write_image(&output.path, blob)?;
+            assets.push(Asset {
+                path: output.path.clone(),
+                kind: AssetKind::from(&planned.file.kind),
+                id: planned.file.id.to_string(),
+                name: planned.file.name.to_string(),
+                alternate_art: planned.file.alternate_art.as_deref().map(Into::into),
+                variant: output.size.suffix(),
+                width: Some(image.width()),
+                height: Some(image.height()),
+                size: blob.len() as u64,
+                hash: hash.clone(),
+            });
+        }
+
+        Ok(Some(assets))
+    }
+
+    fn outputs(&self, file: &File) -> Vec<Output> {
+        let sizes = self.file_sizes(file);
+        let formats = self
+            .format_overrides
+            .iter()
+            .find(|(m, _)| m.matches(file))
+            .map_or(&self.formats, |(_, formats)| formats);
+
+        let mut outputs = Vec::<Output>::new();
+        for name in self.output_names(file) {
+            for &size in sizes {
+                for &format in formats {
+                    let path = output_path(&name, size, format);
+                    if !outputs.iter().any(|output| output.path == path) {
+                        outputs.push(Output {
+                            name: name.clone(),
+                            path,
+                            size,
+                            format,
+                        });
+                    }
+                }
+            }
+        }
+        outputs
+    }
+
+    /// Configured output sizes of a file, never empty.
+    fn file_sizes(&self, file: &File) -> &[OutputSize] {
+        let sizes = self
+            .size_overrides
+            .iter()
+            .find(|(m, _)| m.matches(file))
+            .map_or(&self.sizes, |(_, sizes)| sizes);
+
+        match sizes.is_empty() {
+            true => &[OutputSize::Original],
+            false => sizes,
+        }
+    }
+
+    fn output_names(&self, file: &File) -> Vec<String> {
+        match file.kind {
+            Kind::Art { .. } => vec![file.name.to_string()],
+            Kind::File => vec![file.id.strip_suffix(".dds").unwrap_or(&file.id).to_owned()],
+            Kind::Base | Kind::Unique => self
+                .names(file)
+                .map(|name| match &file.alternate_art {
+                    Some(vis) => format!("{name} ({vis})"),
+                    None => name.into_owned(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Describes the processing steps applied to the source texture of a file.
+    fn steps(&self, file: &File) -> Vec<String> {
+        match file.kind {
+            Kind::Art { position, size, .. } => vec![format!(
+                "crop {} {} {} {}",
+                position.0, position.1, size.0, size.1
+            )],
+            _ => self
+                .postprocess
+                .iter()
+                .enumerate()
+                .filter(|(_, (m, _))| m.matches(file))
+                .map(|(i, (_, pp))| format!("postprocess {i} {}", pp.name()))
+                .collect(),
+        }
+    }
+
+    /// Fails if the image backend can not encode any of the configured output formats,
+    /// checked before any work is done.
+    fn check_formats(&self) -> anyhow::Result<()> {
+        let mut formats = self
+            .formats
+            .iter()
+            .chain(
+                self.format_overrides
+                    .iter()
+                    .flat_map(|(_, formats)| formats),
+            )
+            .chain(self.atlases.iter().map(|(_, _, options)| &options.format));
+
+        match formats.find(|format| !format.is_supported()) {
+            Some(format) => {
+                anyhow::bail!("output format '{format}' is not supported by the image backend")
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn apply_postprocess(&self, file: &File, dds: &mut image::Dds) -> anyhow::Result<()> {
+        for (m, pp) in &self.postprocess {
+            if m.matches(file) {
+                pp.postprocess(dds)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_image(&self, path: &str, blob: &[u8]) -> anyhow::Result<()> {
+        let out = self.out.join(path);
+
+        std::fs::create_dir_all(out.parent().unwrap())?;
+        {
+            let mut out = std::fs::File::create(&out)?;
+            out.write_all(blob)?;
+        }
+        Ok(())
+    }
+
+    fn write_font(&self, name: &str, font: &[u8]) -> anyhow::Result<()> {
+        let out = self.out.join(name);
+
+        std::fs::create_dir_all(out.parent().unwrap())?;
+        {
+            let mut out = std::fs::File::create(&out)?;
+            out.write_all(font)?;
+        }
+
+        if name.ends_with(".ttf") {
+            crate::font::ttf_to_woff2(&out)?;
+        }
+
+        Ok(())
+    }
+
+    fn ui_images<F2: BundleFs>(
+        &self,
+        index: &IndexBundle<F2>,
+    ) -> anyhow::Result<impl Iterator<Item = File<'static>> + '_> {
+        let Some(ui_images) = index.read_by_name("Art/UIImages1.txt")? else {
+            anyhow::bail!("Art/UIImages1.txt does not exist");
+        };
+
+        // TODO: this sucks but w/e, need something now
+        let ui_images: String = (&DatString(&ui_images)).try_into()?;
+
+        Ok(ui_images
+            .lines()
+            .filter_map(|line| {
+                let (l, r) = line.split_once("\" \"")?;
+                let name = l.strip_prefix('"')?;
+                let (file, args) = r.split_once("\" ")?;
+
+                let (position, size) = match ui_image_rect(args) {
+                    Ok(rect) => rect,
+                    Err(err) => {
+                        tracing::warn!("skipping ui image '{name}': {err:#}");
+                        return None;
+                    }
+                };
+
+                let f = File {
+                    kind: Kind::Art {
+                        art_file: file.to_owned(),
+                        position,
+                        size,
+                    },
+                    name: Cow::Borrowed(name),
+                    id: Cow::Borrowed(name),
+                    item_visual_identity: None,
+                    alternate_art: None,
+                };
+
+                if self.selectors.iter().any(|s| s.matches(&f)) {
+                    Some(File {
+                        kind: f.kind,
+                        name: Cow::Owned(name.to_owned()),
+                        id: Cow::Owned(name.to_owned()),
+                        item_visual_identity: None,
+                        alternate_art: None,
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>()
+            .into_iter())
+    }
+
+    fn bundle_files<'a, F2: BundleFs>(
+        &'a self,
+        index: &'a IndexBundle<F2>,
+    ) -> anyhow::Result<impl Iterator<Item = BundleResult<File<'static>>> + 'a> {
+        let files = index
+            .files()?
+            .map(|file| {
+                file.map(|file| File {
+                    kind: Kind::File,
+                    id: Cow::Owned(file.clone()),
+                    name: Cow::Owned(file),
+                    item_visual_identity: None,
+                    alternate_art: None,
+                })
+            })
+            .filter(|file| {
+                file.as_ref()
+                    .map_or(true, |file| self.selectors.iter().any(|s| s.matches(file)))
+            });
+
+        Ok(files)
+    }
+
+    fn names<'a>(&'a self, file: &'a File) -> impl Iterator<Item = Cow<'a, str>> + 'a {
+        let mut renames = self.rename.iter().flat_map(|r| r(file)).peekable();
+        if renames.peek().is_some() {
+            itertools::Either::Left(renames)
+        } else {
+            itertools::Either::Right(std::iter::once(Cow::Borrowed(file.name.as_ref())))
+        }
+    }
+}
+
+/// Parses the position and size of a ui image from the inclusive corners `x1 y1 x2 y2`.
+fn ui_image_rect(args: &str) -> anyhow::Result<((u32, u32), (u32, u32))> {
+    let args = args
+        .split_whitespace()
+        .map(|arg| {
+            arg.parse::<u32>()
+                .with_context(|| format!("invalid number '{arg}'"))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let [x1, y1, x2, y2, ..] = args[..] else {
+        anyhow::bail!("expected 4 coordinates, got {}", args.len());
+    };
+
+    let size = |start: u32, end: u32| {
+        end.checked_sub(start)
+            .map(|size| size + 1)
+            .with_context(|| format!("end {end} is before the start {start}"))
+    };
+    Ok(((x1, y1), (size(x1, x2)?, size(y1, y2)?)))
+}
+
+fn base_file(base: BaseItemTypes) -> anyhow::Result<File<'static>> {
+    Ok(File {
+        kind: Kind::Base,
+        id: base.id.try_into().context("invalid id")?,
+        item_visual_identity: base.item_visual_identity,
+        alternate_art: None,
+        name: base.name.try_into().context("invalid name")?,
+    })
+}
+
+fn unique_file(
+    unique: &UniqueStashLayout,
+    words: &DatFile<Words>,
+    vis: &DatFile<ItemVisualIdentity>,
+) -> anyhow::Result<File<'static>> {
+    let (Some(name), Some(item_visual_identity)) = (unique.words, unique.item_visual_identity)
+    else {
+        anyhow::bail!("unique '{unique:?}' has no name or visual identity");
+    };
+
+    // TODO: vis gets queried later again
+    let name = name.resolve(words)?.text2;
+    let id = item_visual_identity.resolve(vis)?.id;
+
+    Ok(File {
+        kind: Kind::Unique,
+        id: id.try_into().context("invalid id")?,
+        item_visual_identity: Some(item_visual_identity),
+        alternate_art: None,
+        name: name.try_into().context("invalid name")?,
+    })
+}
+
+/// Removes outputs of the previous run which are no longer generated.
+///
+/// Paths which do not stay within the output directory are ignored, the manifest
+/// is read from the output directory and can not be trusted.
+fn remove_stale(out: &Path, previous: &Manifest, current: &Manifest) -> anyhow::Result<()> {
+    for path in previous.outputs.keys() {
+        if current.outputs.contains_key(path) {
+            continue;
+        }
+
+        let relative = !path.is_empty()
+            && Path::new(path)
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)));
+        if !relative {
+            tracing::warn!("ignoring stale output '{path}' outside of the output directory");
+            continue;
+        }
+
+        match std::fs::remove_file(out.join(path)) {
+            Ok(()) => tracing::debug!("removed stale output '{path}'"),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    Ok(())
+}
+
+/// Path of an output relative to the output directory.
+fn output_path(name: &str, size: OutputSize, format: OutputFormat) -> String {
+    match size.suffix() {
+        Some(suffix) => format!("{name}@{suffix}.{}", format.extension()),
+        None => format!("{name}.{}", format.extension()),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Kind {
+    Art {
+        art_file: String,
+        position: (u32, u32),
+        size: (u32, u32),
+    },
+    Base,
+    Unique,
+    File,
+}
+
+impl std::fmt::Display for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Art { art_file, .. } => write!(f, "art {art_file}"),
+            Self::Base => write!(f, "base"),
+            Self::Unique => write!(f, "unique"),
+            Self::File => write!(f, "file"),
+        }
+    }
+}
+
+/// Size of an output relative to the processed source texture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputSize {
+    /// The processed texture as is, written as `name`.
+    Original,
+    /// `base` dimensions multiplied by `density`, written as `name@{density}x`.
+    ///
+    /// A density of 1 is written as `name`.
+    Density { base: (u32, u32), density: u32 },
+    /// Scaled to a fixed width keeping the aspect ratio, written as `name@{width}w`.
+    Width(u32),
+}
+
+impl OutputSize {
+    /// Dimensions of the output, `None` if the texture is not resized.
+    fn dimensions(&self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let (w, h) = match *self {
+            Self::Original => return None,
+            Self::Density { base, density } => (base.0 * density, base.1 * density),
+            Self::Width(w) => {
+                let h = (u64::from(height) * u64::from(w) + u64::from(width) / 2)
+                    / u64::from(width.max(1));
+                (w, (h as u32).max(1))
+            }
+        };
+
+        ((w, h) != (width, height)).then_some((w, h))
+    }
+
+    /// Suffix appended to the output name, without the `@`.
+    fn suffix(&self) -> Option<String> {
+        match *self {
+            Self::Original | Self::Density { density: 1, .. } => None,
+            Self::Density { density, .. } => Some(format!("{density}x")),
+            Self::Width(width) => Some(format!("{width}w")),
+        }
+    }
+}
+
+impl std::fmt::Display for OutputSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Original => write!(f, "original"),
+            Self::Density { base, density } => {
+                write!(f, "resize {}x{} @{density}x", base.0, base.1)
+            }
+            Self::Width(width) => write!(f, "resize width {width}"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct File<'a> {
+    pub kind: Kind,
+    pub id: Cow<'a, str>,
+    pub name: Cow<'a, str>,
+    /// Visual identity of the file, its `dds_file` is the source texture.
+    // TODO this should be part of the kind?
+    pub item_visual_identity: Option<ForeignKey<ItemVisualIdentity<'static>>>,
+    /// Id of the alternate art visual identity used by the file.
+    ///
+    /// Alternate art shares the name with the regular art, its outputs are
+    /// named `{name} ({alternate_art})` instead.
+    pub alternate_art: Option<Cow<'a, str>>,
+}
+
+/// An output resolved by [`Pipeline::plan`].
+#[derive(Debug, Clone)]
+pub struct PlannedOutput {
+    /// Path relative to the output directory, the sprite name for sprites.
+    pub path: String,
+    /// Atlas the output is packed into.
+    pub atlas: Option<String>,
+    /// Source texture or file in the bundle.
+    pub source: String,
+    pub kind: Kind,
+    pub id: String,
+    pub name: String,
+    /// Crop, postprocess, resize and encoding steps applied to the source.
+    pub steps: Vec<String>,
+}
+
+pub trait Postprocess: Send + Sync {
+    fn postprocess(&self, image: &mut Image) -> Result<(), ImageError>;
+
+    /// Name of the postprocess, recorded in the manifest to detect configuration changes.
+    ///
+    /// The name must change whenever the postprocess produces different images, otherwise
+    /// incremental runs keep the outputs of the previous postprocess.
+    fn name(&self) -> Cow<'_, str>;
+}
+
+/// A closure with an explicit name, e.g. `("grayscale-v2", |image: &mut Image| ...)`.
+///
+/// Closures can not be told apart by their type, the name has to be changed manually
+/// whenever the closure or its captured values change.
+impl<N, F> Postprocess for (N, F)
+where
+    N: AsRef<str> + Send + Sync,
+    F: Fn(&mut Image) -> Result<(), ImageError> + Send + Sync,
+{
+    fn postprocess(&self, image: &mut Image) -> Result<(), ImageError> {
+        (self.1)(image)
+    }
+
+    fn name(&self) -> Cow<'_, str> {
+        Cow::Borrowed(self.0.as_ref())
+    }
+}
+
+/// An unnamed closure, its outputs are regenerated by every incremental run.
+///
+/// The name is unique to the running process, a changed closure can not be detected
+/// otherwise. Name the closure to keep its outputs across runs, see the impl for `(N, F)`.
+impl<F: Fn(&mut Image) -> Result<(), ImageError> + Send + Sync> Postprocess for F {
+    fn postprocess(&self, image: &mut Image) -> Result<(), ImageError> {
+        self(image)
+    }
+
+    fn name(&self) -> Cow<'_, str> {
+        static NAME: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+
+        let name = NAME.get_or_init(|| {
+            use std::hash::{BuildHasher, Hasher};
+
+            // Randomly seeded, the time and process id make collisions even less likely.
+            let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+            if let Ok(time) = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+                hasher.write_u128(time.as_nanos());
+            }
+            hasher.write_u32(std::process::id());
+            format!("unnamed-{:016x}", hasher.finish())
+        });
+        Cow::Borrowed(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LocalBundleFs;
+
+    fn file() -> File<'static> {
+        File {
+            kind: Kind::Base,
+            id: Cow::Borrowed("Metadata/Items/Gems/SkillGemFireball"),
+            name: Cow::Borrowed("Fireball"),
+            item_visual_identity: None,
+            alternate_art: None,
+        }
+    }
+
+    fn steps(postprocess: impl Postprocess + 'static) -> Vec<String> {
+        let mut pipeline = Pipeline::new(LocalBundleFs::new("."), ".");
+        pipeline.postprocess(|_: &File| true, postprocess);
+        pipeline.steps(&file())
+    }
+
+    #[test]
+    #[cfg(not(feature = "magick"))]
+    fn unsupported_formats_fail_before_reading() {
+        let mut pipeline = Pipeline::new(LocalBundleFs::new("does-not-exist"), ".");
+        pipeline.formats_for(|_: &File| true, [OutputFormat::Avif { quality: 50 }]);
+
+        let err = pipeline.plan().unwrap_err().to_string();
+        assert!(err.contains("'avif q50' is not supported"), "{err}");
+
+        let mut pipeline = Pipeline::new(LocalBundleFs::new("does-not-exist"), ".");
+        let options = AtlasOptions {
+            format: OutputFormat::Webp { quality: 75 },
+            ..Default::default()
+        };
+        pipeline.atlas("sprites", |_: &File| true, options);
+        assert!(pipeline
+            .execute()
+            .unwrap_err()
+            .to_string()
+            .contains("not supported"));
+    }
+
+    #[test]
+    fn closure_steps_use_their_name() {
+        let named = |name| steps((name, |_: &mut Image| Ok(())));
+        assert_eq!(named("v1"), ["postprocess 0 v1"]);
+        assert_ne!(named("v1"), named("v2"));
+    }
+
+    #[test]
+    fn unnamed_closure_steps_are_unique_to_the_run() {
+        let unnamed = steps(|_: &mut Image| Ok(()));
+        assert_eq!(unnamed.len(), 1);
+        assert!(
+            unnamed[0].starts_with("postprocess 0 unnamed-"),
+            "{unnamed:?}"
+        );
+        // Stable within the run, so all outputs of a file share the same steps.
+        assert_eq!(steps(|_: &mut Image| Ok(())), unnamed);
+        assert_ne!(steps(("unnamed", |_: &mut Image| Ok(()))), unnamed);
+    }
+
+    #[test]
+    fn collisions_follow_discovery_order() {
+        // Discovered in the opposite order of their sources.
+        let files = || {
+            let file = |id| File {
+                id: Cow::Borrowed(id),
+                ..file()
+            };
+            vec![
+                ("b.dds".to_owned(), file("B")),
+                ("a.dds".to_owned(), file("A")),
+            ]
+        };
+
+        for (policy, owner) in [
+            (CollisionPolicy::KeepFirst, "B"),
+            (CollisionPolicy::KeepLast, "A"),
+        ] {
+            let mut pipeline = Pipeline::new(LocalBundleFs::new("."), ".");
+            pipeline.collisions(policy);
+
+            let sources = pipeline.plan_files(files()).unwrap();
+            let owners = sources
+                .values()
+                .flatten()
+                .map(|planned| planned.file.id.as_ref())
+                .collect::<Vec<_>>();
+            assert_eq!(owners, [owner], "{policy:?}");
+        }
+    }
+
+    #[test]
+    fn ui_image_rects() {
+        assert_eq!(ui_image_rect("10 20 41 51").unwrap(), ((10, 20), (32, 32)));
+        assert_eq!(
+            ui_image_rect(" 0 0  0 0 extra").unwrap_err().to_string(),
+            "invalid number 'extra'"
+        );
+        assert_eq!(
+            ui_image_rect("0 0 0").unwrap_err().to_string(),
+            "expected 4 coordinates, got 3"
+        );
+        assert!(ui_image_rect("0 0 -1 0").is_err());
+        assert_eq!(
+            ui_image_rect("5 0 4 0").unwrap_err().to_string(),
+            "end 4 is before the start 5"
+        );
+    }
+
+    fn utf16(s: &str) -> Vec<u8> {
+        s.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    /// Single row table with the string `s` at the start of the variable data.
+    fn table(row: Vec<u8>, s: &str) -> Vec<u8> {
+        let mut data = 1u32.to_le_bytes().to_vec();
+        data.extend(row);
+        data.extend([0xbb; 8]);
+        data.extend(utf16(s));
+        data.extend([0, 0]);
+        data
+    }
+
+    #[test]
+    fn invalid_base_strings() {
+        let id = utf16("Metadata/Items/Rings/Ring1");
+        let name = utf16("Iron Ring");
+        let base = |id, name| BaseItemTypes {
+            id: DatString(id),
+            name: DatString(name),
+            drop_level: 1,
+            site_visibility: 1,
+            item_visual_identity: None,
+        };
+
+        let file = base_file(base(&id, &name)).unwrap();
+        assert_eq!(file.id, "Metadata/Items/Rings/Ring1");
+        assert_eq!(file.name, "Iron Ring");
+
+        let surrogate = 0xd800u16.to_le_bytes();
+        let err = base_file(base(&id, &surrogate)).unwrap_err();
+        assert_eq!(err.to_string(), "invalid name");
+    }
+
+    #[test]
+    fn unique_files() {
+        let mut row = vec![0; 56];
+        row[48..].copy_from_slice(&8u64.to_le_bytes());
+        let words = DatFile::<Words>::try_new(table(row, "Kaom's Heart")).unwrap();
+
+        let mut row = vec![0; 301];
+        row[..8].copy_from_slice(&8u64.to_le_bytes());
+        row[8..16].copy_from_slice(&8u64.to_le_bytes());
+        let vis = DatFile::<ItemVisualIdentity>::try_new(table(row, "UniqueBodyStr1")).unwrap();
+
+        let unique = |words, vis| UniqueStashLayout {
+            words: ForeignKey::new(words),
+            item_visual_identity: ForeignKey::new(vis),
+            show_if_empty_challenge_league: true,
+        };
+
+        let file = unique_file(&unique(0, 0), &words, &vis).unwrap();
+        assert_eq!(file.id, "UniqueBodyStr1");
+        assert_eq!(file.name, "Kaom's Heart");
+        assert_eq!(file.item_visual_identity.map(|key| key.index()), Some(0));
+
+        let err = unique_file(&unique(0, 1), &words, &vis).unwrap_err();
+        assert!(err.to_string().contains("row 1 does not exist"), "{err}");
+        let err = unique_file(&unique(3, 0), &words, &vis).unwrap_err();
+        assert!(err.to_string().contains("row 3 does not exist"), "{err}");
+        let err = unique_file(&unique(0, u64::MAX - 1), &words, &vis).unwrap_err();
+        assert!(err.to_string().contains("does not exist"), "{err}");
+    }
+
+    #[test]
+    fn stale_outputs_stay_in_the_output_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        std::fs::create_dir_all(out.join("art")).unwrap();
+        for path in ["outside.png", "out/art/stale.png", "out/art/current.png"] {
+            std::fs::write(dir.path().join(path), []).unwrap();
+        }
+
+        let manifest = |paths: &[&str]| Manifest {
+            outputs: paths
+                .iter()
+                .map(|&path| {
+                    let entry = ManifestEntry {
+                        inputs: Inputs {
+                            source: path.to_owned(),
+                            source_hash: String::new(),
+                            steps: Vec::new(),
+                        },
+                        asset: Asset {
+                            path: path.to_owned(),
+                            kind: AssetKind::File,
+                            id: path.to_owned(),
+                            name: path.to_owned(),
+                            alternate_art: None,
+                            variant: None,
+                            width: None,
+                            height: None,
+                            size: 0,
+                            hash: String::new(),
+                        },
+                    };
+                    (path.to_owned(), entry)
+                })
+                .collect(),
+        };
+
+        let outside = dir.path().join("outside.png");
+        let previous = manifest(&[
+            "",
+            "../outside.png",
+            "art/../../outside.png",
+            &outside.to_string_lossy(),
+            "art/stale.png",
+            "art/current.png",
+            "art/missing.png",
+        ]);
+        remove_stale(&out, &previous, &manifest(&["art/current.png"])).unwrap();
+
+        assert!(outside.exists());
+        assert!(!out.join("art/stale.png").exists());
+        assert!(out.join("art/current.png").exists());
+    }
+}
@@ -0,0 +1,8 @@
+#[derive(pobbin_assets::Row)]
+#[row(file = "Data/Test.datc64")]
+pub struct Test {
+    #[column(u32, i32)]
+    pub id: u32,
+}
+
+fn main() {}
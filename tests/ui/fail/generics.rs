@@ -0,0 +1,20 @@
+#[derive(pobbin_assets::Row)]
+#[row(file = "Data/Test.datc64")]
+pub struct Test<'a, T> {
+    #[column(u32)]
+    pub id: T,
+    #[column(string)]
+    pub name: pobbin_assets::DatString<'a>,
+}
+
+#[derive(pobbin_assets::Row)]
+#[row(file = "Data/Other.datc64")]
+pub struct Other<'a>
+where
+    'a: 'static,
+{
+    #[column(string)]
+    pub name: pobbin_assets::DatString<'a>,
+}
+
+fn main() {}
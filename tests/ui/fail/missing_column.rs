@@ -0,0 +1,9 @@
+#[derive(pobbin_assets::Row)]
+#[row(file = "Data/Test.datc64")]
+pub struct Test {
+    #[column(u32)]
+    pub id: u32,
+    pub name: u32,
+}
+
+fn main() {}
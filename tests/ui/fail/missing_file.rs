@@ -0,0 +1,7 @@
+#[derive(pobbin_assets::Row)]
+pub struct Test {
+    #[column(u32)]
+    pub id: u32,
+}
+
+fn main() {}
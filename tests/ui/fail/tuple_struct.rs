@@ -0,0 +1,5 @@
+#[derive(pobbin_assets::Row)]
+#[row(file = "Data/Test.datc64")]
+pub struct Test(#[column(u32)] u32);
+
+fn main() {}
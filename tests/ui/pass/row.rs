@@ -0,0 +1,39 @@
+use pobbin_assets::{DatFile, DatList, DatString, ForeignKey, Row};
+
+#[derive(Row)]
+#[row(file = "Data/Words.datc64")]
+pub struct Words<'a> {
+    #[column(string, skip = 48)]
+    pub text: DatString<'a>,
+}
+
+#[derive(Row)]
+#[row(file = "Data/Test.datc64")]
+pub struct Test<'a> {
+    #[column(u32)]
+    pub id: u32,
+    #[column(bool, skip = 3)]
+    pub flag: bool,
+    #[column(foreign_row(Words))]
+    pub words: Option<ForeignKey<Words<'static>>>,
+    #[column(row(Test))]
+    pub parent: Option<ForeignKey<Test<'static>>>,
+    #[column(list(u32))]
+    pub values: DatList<'a, pobbin_assets::column::U32>,
+}
+
+#[derive(Row)]
+#[row(file = "Data/Flags.datc64")]
+pub struct Flags {
+    #[column(pobbin_assets::column::Bool)]
+    pub flag: bool,
+}
+
+fn main() {
+    let data = [1, 0, 0, 0, 1, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb];
+    let flags = DatFile::<Flags>::new(&data[..]);
+    assert!(flags.get(0).unwrap().flag);
+
+    let _ = DatFile::<Test>::try_new(&data[..]);
+    let _ = DatFile::<Words>::try_new(&data[..]);
+}